@@ -0,0 +1,167 @@
+//! Command StayAwake keeps the system awake for
+
+use std::{
+    ffi::OsString,
+    io,
+    process::{ExitCode, ExitStatus},
+};
+
+use tokio::process::{Child, Command};
+
+#[derive(Debug)]
+pub(crate) struct ChildCommand {
+    child: Child,
+    program: OsString,
+}
+
+impl ChildCommand {
+    /// Spawns `command` (program followed by its arguments) with inherited stdio
+    pub(crate) fn spawn(command: &[OsString]) -> io::Result<Self> {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+
+        let child = Command::new(program).args(args).spawn()?;
+
+        Ok(Self {
+            child,
+            program: program.clone(),
+        })
+    }
+
+    /// Name of the spawned program
+    pub(crate) fn program(&self) -> &OsString {
+        &self.program
+    }
+
+    /// Process ID of the child, `None` once it has been reaped
+    pub(crate) fn id(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    /// Waits until the child exits
+    pub(crate) async fn wait(&mut self) -> io::Result<ExitStatus> {
+        self.child.wait().await
+    }
+
+    /// Whether the child got `signal` from the terminal already: keyboard signals (Ctrl+C, Ctrl+\\) are delivered to
+    /// the whole foreground process group, which the child shares with StayAwake
+    #[cfg(unix)]
+    pub(crate) fn got_from_terminal(&self, signal: libc::c_int) -> bool {
+        let Some(pid) = self.id() else {
+            return false;
+        };
+
+        matches!(signal, libc::SIGINT | libc::SIGQUIT)
+            && unsafe { libc::getpgid(pid as libc::pid_t) == libc::getpgrp() }
+    }
+
+    /// Sends `signal` to the child, does nothing if it already exited
+    #[cfg(unix)]
+    pub(crate) fn forward(&self, signal: libc::c_int) -> io::Result<()> {
+        let Some(pid) = self.id() else {
+            return Ok(());
+        };
+
+        if unsafe { libc::kill(pid as libc::pid_t, signal) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+/// Exit code StayAwake exits with after the child exited with `status`
+///
+/// A child killed by a signal is reported like shells do, as 128 + signal number.
+pub(crate) fn exit_code(status: ExitStatus) -> ExitCode {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return ExitCode::from(128u8.wrapping_add(signal as u8));
+        }
+    }
+
+    match status.code() {
+        Some(code) => ExitCode::from(exit_byte(code)),
+        None => ExitCode::FAILURE,
+    }
+}
+
+/// Lowest byte of the exit code, as Unix reports it, but never 0 for a failure (e.g. 256 or Windows' `0xC0000100`)
+fn exit_byte(code: i32) -> u8 {
+    match code as u8 {
+        0 if code != 0 => 1,
+        byte => byte,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Vec<OsString> {
+        ["sh", "-c", script].into_iter().map(Into::into).collect()
+    }
+
+    #[tokio::test]
+    async fn test_exit_code() {
+        let mut child = ChildCommand::spawn(&sh("exit 3")).unwrap();
+        assert_eq!(child.program(), "sh");
+
+        let status = child.wait().await.unwrap();
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(exit_code(status), ExitCode::from(3));
+
+        let mut child = ChildCommand::spawn(&sh("true")).unwrap();
+        let status = child.wait().await.unwrap();
+        assert_eq!(exit_code(status), ExitCode::SUCCESS);
+    }
+
+    #[tokio::test]
+    async fn test_forward() {
+        let mut child = ChildCommand::spawn(&sh("sleep 30")).unwrap();
+        child.forward(libc::SIGTERM).unwrap();
+
+        let status = child.wait().await.unwrap();
+        assert_eq!(exit_code(status), ExitCode::from(128 + libc::SIGTERM as u8));
+
+        // The child is gone, forwarding is a no-op
+        child.forward(libc::SIGTERM).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_got_from_terminal() {
+        let mut child = ChildCommand::spawn(&sh("sleep 30")).unwrap();
+
+        // Spawned in the process group of StayAwake
+        assert!(child.got_from_terminal(libc::SIGINT));
+        assert!(child.got_from_terminal(libc::SIGQUIT));
+        assert!(!child.got_from_terminal(libc::SIGTERM));
+        assert!(!child.got_from_terminal(libc::SIGHUP));
+
+        child.forward(libc::SIGKILL).unwrap();
+        child.wait().await.unwrap();
+        assert!(!child.got_from_terminal(libc::SIGINT));
+    }
+
+    #[test]
+    fn test_exit_byte() {
+        assert_eq!(exit_byte(0), 0);
+        assert_eq!(exit_byte(3), 3);
+        assert_eq!(exit_byte(255), 255);
+        assert_eq!(exit_byte(256), 1);
+        assert_eq!(exit_byte(259), 3);
+        assert_eq!(exit_byte(-1), 255);
+        // STATUS_DLL_INIT_FAILED on Windows
+        assert_eq!(exit_byte(0xC000_0100_u32 as i32), 1);
+    }
+
+    #[tokio::test]
+    async fn test_spawn_errors() {
+        assert!(ChildCommand::spawn(&[]).is_err());
+        assert!(ChildCommand::spawn(&["stayawake-no-such-program".into()]).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+//! Command line arguments
+
+use std::ffi::OsString;
+
+use clap::{Parser, Subcommand};
+
+/// Keeps your system awake without affecting your workflow.
+///
+/// The behaviour is configured with environment variables or a `.env` file,
+/// see the README for the available properties.
+#[derive(Parser, Debug, PartialEq)]
+#[command(version, args_conflicts_with_subcommands = true)]
+pub(crate) struct Cli {
+    #[command(subcommand)]
+    pub(crate) subcommand: Option<Command>,
+
+    /// Command to run, StayAwake keeps the system awake until it exits and exits with its exit code
+    ///
+    /// SIGINT, SIGTERM and SIGHUP are forwarded to the command, the configuration is not reloaded while it runs.
+    #[arg(last = true, value_name = "COMMAND")]
+    pub(crate) command: Vec<OsString>,
+
+    /// Keep the system awake until the process with this PID exits (can be repeated)
+    #[arg(long = "watch-pid", value_name = "PID", conflicts_with = "command")]
+    pub(crate) watch_pids: Vec<u32>,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub(crate) enum Command {
+    /// Inspects the policy (`POLICY` or `POLICY_FILE`)
+    Policy {
+        #[command(subcommand)]
+        action: PolicyAction,
+    },
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub(crate) enum PolicyAction {
+    /// Checks the policy once and prints which rule matched and why
+    Explain,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command() {
+        let cli = Cli::try_parse_from(["stayawake"]).unwrap();
+        assert!(cli.command.is_empty());
+
+        let cli =
+            Cli::try_parse_from(["stayawake", "--", "rsync", "-a", "--delete", "src/", "dst/"])
+                .unwrap();
+        assert_eq!(cli.command, ["rsync", "-a", "--delete", "src/", "dst/"]);
+
+        // Arguments of the command must be separated by `--`
+        assert!(Cli::try_parse_from(["stayawake", "rsync"]).is_err());
+    }
+
+    #[test]
+    fn test_watch_pid() {
+        let cli =
+            Cli::try_parse_from(["stayawake", "--watch-pid", "42", "--watch-pid=4242"]).unwrap();
+        assert_eq!(cli.watch_pids, [42, 4242]);
+
+        assert!(Cli::try_parse_from(["stayawake", "--watch-pid", "rsync"]).is_err());
+        assert!(Cli::try_parse_from(["stayawake", "--watch-pid", "42", "--", "rsync"]).is_err());
+    }
+
+    #[test]
+    fn test_policy_explain() {
+        let cli = Cli::try_parse_from(["stayawake", "policy", "explain"]).unwrap();
+        assert_eq!(
+            cli.subcommand,
+            Some(Command::Policy {
+                action: PolicyAction::Explain
+            })
+        );
+
+        assert!(Cli::try_parse_from(["stayawake", "policy"]).is_err());
+        assert!(
+            Cli::try_parse_from(["stayawake", "--watch-pid", "42", "policy", "explain"]).is_err()
+        );
+    }
+}
@@ -0,0 +1,370 @@
+//! Keeping the system awake while the CPU, disks or network are busy
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use tracing::{debug, info};
+
+use super::Condition;
+
+/// Usage above which the system is considered busy, `None` thresholds are not checked
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct Thresholds {
+    pub(crate) cpu_percent: Option<u8>,
+    pub(crate) disk_kbps: Option<u64>,
+    pub(crate) net_kbps: Option<u64>,
+}
+
+/// Cumulative counters read from `/proc`
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at: Instant,
+    /// CPU time spent working, in clock ticks
+    cpu_busy: u64,
+    /// All CPU time, in clock ticks
+    cpu_total: u64,
+    /// Bytes read from and written to disks
+    disk_bytes: u64,
+    /// Bytes received and sent over network interfaces, except loopback
+    net_bytes: u64,
+}
+
+/// Usage between two samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Usage {
+    cpu_percent: f64,
+    disk_kbps: f64,
+    net_kbps: f64,
+}
+
+#[derive(Debug)]
+pub(crate) struct Activity {
+    thresholds: Thresholds,
+    /// How long the system stays awake after the last busy sample
+    quiet_period: Duration,
+    proc_root: PathBuf,
+    /// Where sysfs is mounted, it tells partitions apart from whole disks
+    sys_root: PathBuf,
+    previous: Option<Sample>,
+    last_busy: Option<Instant>,
+    holds: bool,
+}
+
+impl Activity {
+    pub(crate) fn new(thresholds: Thresholds, quiet_period: Duration) -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            anyhow::bail!("CPU, disk and network activity can only be monitored on Linux");
+        }
+
+        Ok(Self::with_root(
+            thresholds,
+            quiet_period,
+            PathBuf::from("/proc"),
+            PathBuf::from("/sys"),
+        ))
+    }
+
+    fn with_root(
+        thresholds: Thresholds,
+        quiet_period: Duration,
+        proc_root: PathBuf,
+        sys_root: PathBuf,
+    ) -> Self {
+        Self {
+            thresholds,
+            quiet_period,
+            proc_root,
+            sys_root,
+            previous: None,
+            last_busy: None,
+            holds: false,
+        }
+    }
+
+    fn sample(&self, at: Instant) -> Sample {
+        let read = |name: &str| fs::read_to_string(self.proc_root.join(name)).unwrap_or_default();
+        let (cpu_busy, cpu_total) = parse_cpu(&read("stat"));
+
+        Sample {
+            at,
+            cpu_busy,
+            cpu_total,
+            disk_bytes: parse_disk_bytes(&read("diskstats"), |name| {
+                is_partition(&self.sys_root, name)
+            }),
+            net_bytes: parse_net_bytes(&read("net/dev")),
+        }
+    }
+
+    fn is_busy(&self, usage: &Usage) -> bool {
+        let above = |threshold: Option<u64>, value: f64| {
+            threshold.is_some_and(|threshold| value >= threshold as f64)
+        };
+
+        above(
+            self.thresholds.cpu_percent.map(u64::from),
+            usage.cpu_percent,
+        ) || above(self.thresholds.disk_kbps, usage.disk_kbps)
+            || above(self.thresholds.net_kbps, usage.net_kbps)
+    }
+
+    fn check_at(&mut self, now: Instant) -> bool {
+        let sample = self.sample(now);
+
+        if let Some(previous) = self.previous.replace(sample) {
+            let usage = usage(&previous, &sample);
+            debug!(?usage, "System activity");
+
+            if self.is_busy(&usage) {
+                self.last_busy = Some(now);
+            }
+        }
+
+        let holds = self
+            .last_busy
+            .is_some_and(|last_busy| now - last_busy < self.quiet_period);
+
+        if holds != self.holds {
+            if holds {
+                info!("System is busy");
+            } else {
+                info!(quiet_period = ?self.quiet_period, "System has been quiet for the whole quiet period");
+            }
+            self.holds = holds;
+        }
+
+        holds
+    }
+}
+
+impl Condition for Activity {
+    fn name(&self) -> &'static str {
+        "activity"
+    }
+
+    fn check(&mut self) -> bool {
+        self.check_at(Instant::now())
+    }
+}
+
+fn usage(previous: &Sample, current: &Sample) -> Usage {
+    let seconds = (current.at - previous.at).as_secs_f64();
+    let kbps = |previous: u64, current: u64| {
+        if seconds > 0.0 {
+            current.saturating_sub(previous) as f64 / 1024.0 / seconds
+        } else {
+            0.0
+        }
+    };
+
+    let cpu_total = current.cpu_total.saturating_sub(previous.cpu_total);
+    let cpu_busy = current.cpu_busy.saturating_sub(previous.cpu_busy);
+    let cpu_percent = if cpu_total > 0 {
+        cpu_busy as f64 * 100.0 / cpu_total as f64
+    } else {
+        0.0
+    };
+
+    Usage {
+        cpu_percent,
+        disk_kbps: kbps(previous.disk_bytes, current.disk_bytes),
+        net_kbps: kbps(previous.net_bytes, current.net_bytes),
+    }
+}
+
+/// Busy and total CPU time from the aggregate `cpu` line of `/proc/stat`
+fn parse_cpu(stat: &str) -> (u64, u64) {
+    let Some(line) = stat.lines().find(|line| line.starts_with("cpu ")) else {
+        return (0, 0);
+    };
+
+    // user nice system idle iowait irq softirq steal (guest time is already part of user time)
+    let times: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .take(8)
+        .map(|value| value.parse().unwrap_or(0))
+        .collect();
+
+    let total: u64 = times.iter().sum();
+    let idle = times.get(3).copied().unwrap_or(0) + times.get(4).copied().unwrap_or(0);
+
+    (total.saturating_sub(idle), total)
+}
+
+/// Whether the block device is a partition, which sysfs tells with a `partition` attribute
+fn is_partition(sys_root: &Path, name: &str) -> bool {
+    sys_root
+        .join("class/block")
+        .join(name)
+        .join("partition")
+        .exists()
+}
+
+/// Bytes transferred by physical disks according to `/proc/diskstats`
+fn parse_disk_bytes(diskstats: &str, is_partition: impl Fn(&str) -> bool) -> u64 {
+    /// Sectors in `/proc/diskstats` are always 512 bytes long
+    const SECTOR_SIZE: u64 = 512;
+
+    diskstats
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let name = *fields.get(2)?;
+            let read: u64 = fields.get(5)?.parse().ok()?;
+            let written: u64 = fields.get(9)?.parse().ok()?;
+            Some((name, read + written))
+        })
+        // Virtual devices only pass the transfers on to the physical ones
+        .filter(|(name, _)| {
+            !["loop", "ram", "zram", "dm-", "md"]
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        })
+        // Partitions (sda1, nvme0n1p1) are already counted by their disk
+        .filter(|(name, _)| !is_partition(name))
+        .map(|(_, sectors)| sectors * SECTOR_SIZE)
+        .sum()
+}
+
+/// Bytes received and sent by network interfaces according to `/proc/net/dev`
+fn parse_net_bytes(net_dev: &str) -> u64 {
+    net_dev
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(interface, _)| interface.trim() != "lo")
+        .filter_map(|(_, counters)| {
+            let counters: Vec<&str> = counters.split_whitespace().collect();
+            let received: u64 = counters.first()?.parse().ok()?;
+            let sent: u64 = counters.get(8)?.parse().ok()?;
+            Some(received + sent)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STAT: &str =
+        "cpu  100 0 100 700 100 0 0 0 50 0\ncpu0 50 0 50 350 50 0 0 0 25 0\nintr 1 2 3\n";
+
+    const DISKSTATS: &str = "   7       0 loop0 10 0 4000 10 0 0 0 0 0 10 10 0 0 0 0
+   8       0 sda 100 10 1000 50 20 5 3000 40 0 60 90 0 0 0 0
+   8       1 sda1 90 10 900 45 20 5 3000 40 0 55 85 0 0 0 0
+ 259       0 nvme0n1 10 0 96 1 1 0 8 0 0 1 1 0 0 0 0
+ 259       1 nvme0n1p1 10 0 96 1 1 0 8 0 0 1 1 0 0 0 0
+ 253       0 dm-0 10 0 500 1 1 0 500 0 0 1 1 0 0 0 0
+";
+
+    const NET_DEV: &str = "Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo: 9000       10    0    0    0     0          0         0     9000      10    0    0    0     0       0          0
+  eth0: 2048       10    0    0    0     0          0         0     1024      10    0    0    0     0       0          0
+ wlan0: 1024       10    0    0    0     0          0         0        0       0    0    0    0     0       0          0
+";
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_cpu(STAT), (200, 1000));
+        assert_eq!(parse_cpu(""), (0, 0));
+        assert_eq!(
+            parse_disk_bytes(DISKSTATS, |name| ["sda1", "nvme0n1p1"].contains(&name)),
+            (1000 + 3000 + 96 + 8) * 512
+        );
+        assert_eq!(parse_net_bytes(NET_DEV), 2048 + 1024 + 1024);
+    }
+
+    #[test]
+    fn test_disk_names() {
+        // The 27th SCSI disk and the 10th NVMe namespace start with the name of another disk
+        const DISKSTATS: &str = "   8       0 sda 0 0 1 0 0 0 0 0 0 0 0 0 0 0 0
+   8       1 sda1 0 0 1 0 0 0 0 0 0 0 0 0 0 0 0
+  65     160 sdaa 0 0 10 0 0 0 0 0 0 0 0 0 0 0 0
+  65     161 sdaa1 0 0 10 0 0 0 0 0 0 0 0 0 0 0 0
+ 259       0 nvme0n1 0 0 100 0 0 0 0 0 0 0 0 0 0 0 0
+ 259       1 nvme0n1p1 0 0 100 0 0 0 0 0 0 0 0 0 0 0 0
+ 259       2 nvme0n10 0 0 1000 0 0 0 0 0 0 0 0 0 0 0 0
+";
+
+        let sys = tempfile::tempdir().unwrap();
+        for name in ["sda", "sdaa", "nvme0n1", "nvme0n10"] {
+            fs::create_dir_all(sys.path().join("class/block").join(name)).unwrap();
+        }
+        for (name, number) in [("sda1", 1), ("sdaa1", 1), ("nvme0n1p1", 1)] {
+            let dir = sys.path().join("class/block").join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("partition"), format!("{number}\n")).unwrap();
+        }
+
+        assert!(!is_partition(sys.path(), "sdaa"));
+        assert!(is_partition(sys.path(), "sdaa1"));
+        assert_eq!(
+            parse_disk_bytes(DISKSTATS, |name| is_partition(sys.path(), name)),
+            (1 + 10 + 100 + 1000) * 512
+        );
+    }
+
+    fn write_proc(root: &std::path::Path, cpu: (u64, u64), disk_sectors: u64, net_bytes: u64) {
+        fs::create_dir_all(root.join("net")).unwrap();
+        let (busy, idle) = cpu;
+        fs::write(
+            root.join("stat"),
+            format!("cpu  {busy} 0 0 {idle} 0 0 0 0 0 0\n"),
+        )
+        .unwrap();
+        fs::write(
+            root.join("diskstats"),
+            format!("   8       0 sda 1 0 {disk_sectors} 0 0 0 0 0 0 0 0 0 0 0 0\n"),
+        )
+        .unwrap();
+        fs::write(
+            root.join("net/dev"),
+            format!("  eth0: {net_bytes} 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let root = tempfile::tempdir().unwrap();
+        let thresholds = Thresholds {
+            cpu_percent: Some(20),
+            disk_kbps: None,
+            net_kbps: Some(500),
+        };
+        let mut activity = Activity::with_root(
+            thresholds,
+            Duration::from_secs(60),
+            root.path().to_owned(),
+            root.path().join("sys"),
+        );
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+
+        write_proc(root.path(), (0, 0), 0, 0);
+        assert!(!activity.check_at(at(0)));
+
+        // 10% CPU, disks are not checked, 100 kB/s of network traffic
+        write_proc(root.path(), (10, 90), 1_000_000, 1024 * 1000);
+        assert!(!activity.check_at(at(10)));
+
+        // 600 kB/s of network traffic
+        write_proc(root.path(), (20, 180), 1_000_000, 1024 * 7000);
+        assert!(activity.check_at(at(20)));
+
+        // 50% CPU
+        write_proc(root.path(), (70, 230), 1_000_000, 1024 * 7000);
+        assert!(activity.check_at(at(30)));
+
+        // Quiet, but still within the quiet period
+        write_proc(root.path(), (70, 330), 1_000_000, 1024 * 7000);
+        assert!(activity.check_at(at(89)));
+
+        assert!(!activity.check_at(at(90)));
+    }
+}
@@ -0,0 +1,993 @@
+//! Keeping the system awake during the busy events of an iCalendar file (`CALENDAR_ICS`)
+//!
+//! Recurring events support the `DAILY`, `WEEKLY`, `MONTHLY` and `YEARLY` frequencies with the `INTERVAL`, `COUNT`,
+//! `UNTIL`, `BYDAY`, `BYMONTHDAY`, `BYMONTH` and `BYSETPOS` parts of `RRULE`. Occurrences can be added with `RDATE`,
+//! removed with `EXDATE` and replaced by events with the same `UID` and a `RECURRENCE-ID`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{
+    DateTime, Datelike, Days, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc, Weekday,
+};
+use ical::{parser::ical::component::IcalEvent, property::Property, IcalParser};
+use regex::Regex;
+use tracing::{info, warn};
+
+use super::{time::Zone, Condition};
+
+/// Periods of a recurrence rule expanded at most, in case its parts never match
+const MAX_PERIODS: u32 = 100_000;
+
+/// How far past the horizon occurrences are expanded, so that rules are not expanded again on every check
+const EXPANSION_DAYS: i64 = 7;
+
+/// Events taken into account, all of them if empty
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Filters {
+    /// Matched anywhere in the summary
+    pub(crate) summary: Option<Regex>,
+    /// Any of them has to be among the categories of the event, case-insensitive
+    pub(crate) categories: Vec<String>,
+}
+
+impl Filters {
+    // `Option::is_none_or` needs a newer compiler than the one supported
+    #[allow(clippy::unnecessary_map_or)]
+    fn matches(&self, event: &Event) -> bool {
+        self.summary
+            .as_ref()
+            .map_or(true, |summary| summary.is_match(&event.summary))
+            && (self.categories.is_empty()
+                || event.categories.iter().any(|category| {
+                    self.categories
+                        .iter()
+                        .any(|filter| filter.eq_ignore_ascii_case(category))
+                }))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// Recurrence rule (`RRULE`) of an event
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    frequency: Frequency,
+    interval: u32,
+    count: Option<u32>,
+    until: Option<DateTime<Utc>>,
+    /// Days of the week, e.g. `MO`, optionally the n-th one of the month (`1MO`) or the last one (`-1FR`)
+    by_day: Vec<(Option<i32>, Weekday)>,
+    by_month_day: Vec<i32>,
+    by_month: Vec<u32>,
+    by_set_pos: Vec<i32>,
+}
+
+fn parse_weekday(s: &str) -> Result<Weekday> {
+    Ok(match s {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => bail!("invalid day of the week `{s}`"),
+    })
+}
+
+fn parse_list<T: FromStr>(s: &str) -> Result<Vec<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    Ok(s.split(',').map(str::parse).collect::<Result<_, _>>()?)
+}
+
+impl Rule {
+    /// Parses e.g. `FREQ=WEEKLY;BYDAY=MO,WE;UNTIL=20261231T235959Z`, floating times are in `zone`
+    fn parse(s: &str, zone: Zone) -> Result<Self> {
+        let mut frequency = None;
+        let mut rule = Rule {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+            by_set_pos: Vec::new(),
+        };
+
+        for part in s.split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| anyhow!("invalid rule part `{part}`"))?;
+
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => bail!("unsupported frequency `{value}`"),
+                    })
+                }
+                "INTERVAL" => rule.interval = value.parse()?,
+                "COUNT" => rule.count = Some(value.parse()?),
+                "UNTIL" => {
+                    let (until, date) = parse_time(value, zone)?;
+                    // The whole last day is included
+                    rule.until = Some(if date {
+                        until + TimeDelta::days(1) - TimeDelta::seconds(1)
+                    } else {
+                        until
+                    });
+                }
+                "BYDAY" => {
+                    rule.by_day = value
+                        .split(',')
+                        .map(|day| {
+                            let (n, weekday) = day.split_at(day.len().saturating_sub(2));
+                            let n = if n.is_empty() { None } else { Some(n.parse()?) };
+                            Ok((n, parse_weekday(weekday)?))
+                        })
+                        .collect::<Result<_>>()?
+                }
+                "BYMONTHDAY" => rule.by_month_day = parse_list(value)?,
+                "BYMONTH" => rule.by_month = parse_list(value)?,
+                "BYSETPOS" => rule.by_set_pos = parse_list(value)?,
+                // The week always starts on Monday
+                "WKST" => {}
+                _ => bail!("unsupported rule part `{name}`"),
+            }
+        }
+
+        rule.frequency = frequency.ok_or_else(|| anyhow!("missing FREQ"))?;
+        if rule.interval == 0 {
+            bail!("INTERVAL cannot be equal to zero");
+        }
+
+        Ok(rule)
+    }
+
+    /// Starts of the occurrences, from the first one at `start` until `horizon`
+    fn expand(
+        &self,
+        start: NaiveDateTime,
+        zone: Zone,
+        horizon: DateTime<Utc>,
+    ) -> Vec<DateTime<Utc>> {
+        let mut starts = Vec::new();
+
+        for period in 0..MAX_PERIODS {
+            let Some(mut candidates) = self.candidates(start, period) else {
+                break;
+            };
+            candidates.sort();
+            candidates.dedup();
+
+            if !self.by_set_pos.is_empty() {
+                candidates = select(&candidates, &self.by_set_pos);
+            }
+
+            for local in candidates.into_iter().filter(|local| *local >= start) {
+                let time = zone.utc(local);
+
+                if time > horizon
+                    || self.until.is_some_and(|until| time > until)
+                    || self
+                        .count
+                        .is_some_and(|count| starts.len() >= count as usize)
+                {
+                    return starts;
+                }
+
+                starts.push(time);
+            }
+        }
+
+        starts
+    }
+
+    /// Local times of the occurrences in the `period`-th day, week, month or year, `None` if out of range
+    fn candidates(&self, start: NaiveDateTime, period: u32) -> Option<Vec<NaiveDateTime>> {
+        let step = period.checked_mul(self.interval)?;
+        let date = start.date();
+
+        let dates = match self.frequency {
+            Frequency::Daily => {
+                let date = date.checked_add_days(Days::new(step.into()))?;
+                let day_matches = self.by_day.is_empty()
+                    || self
+                        .by_day
+                        .iter()
+                        .any(|(_, weekday)| *weekday == date.weekday());
+                let month_day_matches = self.by_month_day.is_empty()
+                    || self.by_month_day.contains(&(date.day() as i32));
+
+                if day_matches && month_day_matches {
+                    vec![date]
+                } else {
+                    Vec::new()
+                }
+            }
+            Frequency::Weekly => {
+                let monday = date
+                    .checked_sub_days(Days::new(date.weekday().num_days_from_monday().into()))?
+                    .checked_add_days(Days::new(u64::from(step) * 7))?;
+
+                if self.by_day.is_empty() {
+                    vec![monday.checked_add_days(Days::new(
+                        date.weekday().num_days_from_monday().into(),
+                    ))?]
+                } else {
+                    self.by_day
+                        .iter()
+                        .filter_map(|(_, weekday)| {
+                            monday
+                                .checked_add_days(Days::new(weekday.num_days_from_monday().into()))
+                        })
+                        .collect()
+                }
+            }
+            Frequency::Monthly => {
+                let month = date.with_day(1)?.checked_add_months(Months::new(step))?;
+                self.month_dates(month, date.day())
+            }
+            Frequency::Yearly => {
+                let year = date.year().checked_add(step.try_into().ok()?)?;
+                let months = if self.by_month.is_empty() {
+                    vec![date.month()]
+                } else {
+                    self.by_month.clone()
+                };
+
+                months
+                    .into_iter()
+                    .filter_map(|month| NaiveDate::from_ymd_opt(year, month, 1))
+                    .flat_map(|month| self.month_dates(month, date.day()))
+                    .collect()
+            }
+        };
+
+        Some(
+            dates
+                .into_iter()
+                .filter(|date| self.by_month.is_empty() || self.by_month.contains(&date.month()))
+                .map(|date| date.and_time(start.time()))
+                .collect(),
+        )
+    }
+
+    /// Days of the month starting on `first` matching the rule, or its `default` day
+    fn month_dates(&self, first: NaiveDate, default: u32) -> Vec<NaiveDate> {
+        let days_in_month = first.checked_add_months(Months::new(1)).map_or(31, |next| {
+            next.signed_duration_since(first).num_days() as i32
+        });
+        let all: Vec<NaiveDate> = first.iter_days().take(days_in_month as usize).collect();
+
+        if !self.by_month_day.is_empty() {
+            let days: HashSet<i32> = self
+                .by_month_day
+                .iter()
+                .map(|&day| {
+                    if day < 0 {
+                        days_in_month + 1 + day
+                    } else {
+                        day
+                    }
+                })
+                .collect();
+
+            all.into_iter()
+                .filter(|date| days.contains(&(date.day() as i32)))
+                .filter(|date| {
+                    self.by_day.is_empty()
+                        || self
+                            .by_day
+                            .iter()
+                            .any(|(_, weekday)| *weekday == date.weekday())
+                })
+                .collect()
+        } else if !self.by_day.is_empty() {
+            self.by_day
+                .iter()
+                .flat_map(|&(n, weekday)| {
+                    let dates: Vec<NaiveDate> = all
+                        .iter()
+                        .copied()
+                        .filter(|date| date.weekday() == weekday)
+                        .collect();
+
+                    match n {
+                        Some(n) => select(&dates, &[n]),
+                        None => dates,
+                    }
+                })
+                .collect()
+        } else {
+            all.into_iter()
+                .filter(|date| date.day() == default)
+                .collect()
+        }
+    }
+}
+
+/// Items at the given positions, starting at 1 or counted from the end if negative
+fn select<T: Copy>(items: &[T], positions: &[i32]) -> Vec<T> {
+    positions
+        .iter()
+        .filter_map(|&position| {
+            let index = if position < 0 {
+                items.len().checked_sub(position.unsigned_abs() as usize)?
+            } else {
+                (position as usize).checked_sub(1)?
+            };
+            items.get(index).copied()
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+struct Event {
+    uid: Option<String>,
+    summary: String,
+    categories: Vec<String>,
+    /// Local time of the start of the first occurrence
+    start: NaiveDateTime,
+    zone: Zone,
+    duration: TimeDelta,
+    rule: Option<Rule>,
+    added: Vec<DateTime<Utc>>,
+    excluded: Vec<DateTime<Utc>>,
+    /// Start of the occurrence of a recurring event replaced by this one
+    recurrence_id: Option<DateTime<Utc>>,
+    /// Free (`TRANSP:TRANSPARENT`) and cancelled events don't keep the system awake
+    busy: bool,
+}
+
+/// Parses `20261016T090000Z`, `20261016T090000` or `20261016` in `zone` unless in UTC, returns whether it is a date
+fn parse_time(s: &str, zone: Zone) -> Result<(DateTime<Utc>, bool)> {
+    if let Some(utc) = s.strip_suffix('Z') {
+        return Ok((
+            NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")?.and_utc(),
+            false,
+        ));
+    }
+
+    match NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%S") {
+        Ok(local) => Ok((zone.utc(local), false)),
+        Err(_) => {
+            let date = NaiveDate::parse_from_str(s, "%Y%m%d")
+                .with_context(|| format!("invalid date or time `{s}`"))?;
+            Ok((zone.utc(date.and_time(NaiveTime::MIN)), true))
+        }
+    }
+}
+
+/// Parses e.g. `PT1H30M`, `P1D` or `P2W`
+fn parse_duration(s: &str) -> Result<TimeDelta> {
+    let invalid = || anyhow!("invalid duration `{s}`");
+    let (sign, rest) = match s.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+
+    let mut seconds = 0;
+    let mut number = String::new();
+    let mut time = false;
+
+    for c in rest.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' if number.is_empty() => time = true,
+            _ => {
+                let n: i64 = number.parse().map_err(|_| invalid())?;
+                number.clear();
+                seconds += n * match (c, time) {
+                    ('W', false) => 7 * 24 * 3600,
+                    ('D', false) => 24 * 3600,
+                    ('H', true) => 3600,
+                    ('M', true) => 60,
+                    ('S', true) => 1,
+                    _ => return Err(invalid()),
+                };
+            }
+        }
+    }
+
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(TimeDelta::seconds(sign * seconds))
+}
+
+/// Removes the escaping of a text value
+fn unescape(s: &str) -> String {
+    let mut text = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n' | 'N')) => {
+                chars.next();
+                text.push('\n');
+            }
+            ('\\', Some(escaped @ ('\\' | ',' | ';'))) => {
+                chars.next();
+                text.push(escaped);
+            }
+            _ => text.push(c),
+        }
+    }
+
+    text
+}
+
+fn param<'a>(property: &'a Property, name: &str) -> Option<&'a str> {
+    property
+        .params
+        .iter()
+        .flatten()
+        .find(|(param, _)| param.eq_ignore_ascii_case(name))
+        .and_then(|(_, values)| values.first())
+        .map(|value| value.trim_matches('"'))
+}
+
+/// Zone of the `TZID` parameter, the local one for unknown (e.g. Windows) time zones
+fn zone(property: &Property) -> Zone {
+    match param(property, "TZID") {
+        Some(tzid) => tzid.parse().map_or(Zone::Local, Zone::Tz),
+        None => Zone::Local,
+    }
+}
+
+impl Event {
+    fn parse(event: &IcalEvent) -> Result<Self> {
+        let properties = |name: &'static str| {
+            event
+                .properties
+                .iter()
+                .filter(move |property| property.name.eq_ignore_ascii_case(name))
+        };
+        let value = |name| {
+            properties(name)
+                .next()
+                .and_then(|property| property.value.as_deref())
+        };
+        let times = |name| -> Result<Vec<DateTime<Utc>>> {
+            let mut times = Vec::new();
+            for property in properties(name) {
+                for value in property.value.iter().flat_map(|value| value.split(',')) {
+                    times.push(parse_time(value, zone(property))?.0);
+                }
+            }
+            Ok(times)
+        };
+
+        let dtstart = properties("DTSTART")
+            .next()
+            .ok_or_else(|| anyhow!("missing DTSTART"))?;
+        let start_value = dtstart.value.as_deref().unwrap_or_default();
+        let (start_time, date) = parse_time(start_value, zone(dtstart))?;
+        let zone = if start_value.ends_with('Z') {
+            Zone::Utc
+        } else {
+            zone(dtstart)
+        };
+
+        let duration = match (properties("DTEND").next(), value("DURATION")) {
+            (Some(dtend), _) => {
+                let end = dtend.value.as_deref().unwrap_or_default();
+                parse_time(end, self::zone(dtend))?.0 - start_time
+            }
+            (None, Some(duration)) => parse_duration(duration)?,
+            (None, None) if date => TimeDelta::days(1),
+            (None, None) => TimeDelta::zero(),
+        };
+
+        Ok(Event {
+            uid: value("UID").map(str::to_owned),
+            summary: value("SUMMARY").map(unescape).unwrap_or_default(),
+            categories: properties("CATEGORIES")
+                .filter_map(|property| property.value.as_deref())
+                .flat_map(|value| value.split(','))
+                .map(|category| unescape(category.trim()))
+                .collect(),
+            start: zone.local(start_time),
+            zone,
+            duration,
+            rule: value("RRULE")
+                .map(|rule| Rule::parse(rule, zone))
+                .transpose()?,
+            added: times("RDATE")?,
+            excluded: times("EXDATE")?,
+            recurrence_id: times("RECURRENCE-ID")?.first().copied(),
+            busy: !value("TRANSP").is_some_and(|transp| transp.eq_ignore_ascii_case("TRANSPARENT"))
+                && !value("STATUS").is_some_and(|status| status.eq_ignore_ascii_case("CANCELLED")),
+        })
+    }
+
+    /// Starts of the occurrences until `horizon`
+    fn starts(&self, horizon: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+        let mut starts = match &self.rule {
+            Some(rule) => rule.expand(self.start, self.zone, horizon),
+            None => vec![self.zone.utc(self.start)],
+        };
+
+        starts.extend(self.added.iter().filter(|start| **start <= horizon));
+        starts.retain(|start| !self.excluded.contains(start));
+        starts
+    }
+}
+
+fn load(path: &Path) -> Result<Vec<Event>> {
+    let file = File::open(path)?;
+    let mut events = Vec::new();
+
+    for calendar in IcalParser::new(BufReader::new(file)) {
+        for event in calendar?.events {
+            match Event::parse(&event) {
+                Ok(event) => events.push(event),
+                Err(err) => warn!(path = %path.display(), error = %err, "Ignoring calendar event"),
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+#[derive(Debug)]
+pub(crate) struct Calendar {
+    path: PathBuf,
+    filters: Filters,
+    /// How long before the start of the events the system is kept awake
+    lead_time: TimeDelta,
+    events: Vec<Event>,
+    /// Starts of the occurrences replaced by another event, by `UID`
+    replaced: HashMap<String, HashSet<DateTime<Utc>>>,
+    /// Starts of the occurrences of each event, until `expanded_until`
+    starts: Vec<Vec<DateTime<Utc>>>,
+    /// Occurrences are expanded again once the horizon passes it or the file changes
+    expanded_until: Option<DateTime<Utc>>,
+    /// Modification time and size of the loaded file
+    loaded: Option<(SystemTime, u64)>,
+    /// Summary of the event keeping the system awake
+    current: Option<String>,
+}
+
+impl Calendar {
+    pub(crate) fn new(path: &Path, filters: Filters, minutes_before: u64) -> Result<Self> {
+        let mut calendar = Self {
+            path: path.to_owned(),
+            filters,
+            lead_time: TimeDelta::minutes(minutes_before.try_into()?),
+            events: Vec::new(),
+            replaced: HashMap::new(),
+            starts: Vec::new(),
+            expanded_until: None,
+            loaded: None,
+            current: None,
+        };
+
+        calendar.reload()?;
+
+        Ok(calendar)
+    }
+
+    /// Loads the file again if it changed since it was loaded
+    fn reload(&mut self) -> Result<()> {
+        let metadata = fs::metadata(&self.path)
+            .with_context(|| format!("Cannot read calendar {}", self.path.display()))?;
+        let version = (metadata.modified()?, metadata.len());
+
+        if self.loaded != Some(version) {
+            self.events = load(&self.path)
+                .with_context(|| format!("Invalid calendar {}", self.path.display()))?;
+            if self.loaded.is_some() {
+                info!(path = %self.path.display(), events = self.events.len(), "Calendar reloaded");
+            }
+
+            self.replaced.clear();
+            for event in &self.events {
+                if let (Some(uid), Some(recurrence_id)) = (&event.uid, event.recurrence_id) {
+                    self.replaced
+                        .entry(uid.clone())
+                        .or_default()
+                        .insert(recurrence_id);
+                }
+            }
+
+            self.loaded = Some(version);
+            self.expanded_until = None;
+        }
+
+        Ok(())
+    }
+
+    /// Expands the occurrences of the events some time past `horizon`, unless they already are
+    fn expand(&mut self, horizon: DateTime<Utc>) {
+        if self.expanded_until.is_some_and(|until| horizon <= until) {
+            return;
+        }
+
+        let until = horizon + TimeDelta::days(EXPANSION_DAYS);
+        self.starts = self
+            .events
+            .iter()
+            .map(|event| event.starts(until))
+            .collect();
+        self.expanded_until = Some(until);
+    }
+
+    /// Summary of an event happening at `now`, the occurrences have to be expanded until `now` plus the lead time
+    fn current_event(&self, now: DateTime<Utc>) -> Option<&str> {
+        self.events
+            .iter()
+            .zip(&self.starts)
+            .filter(|(event, _)| event.busy && self.filters.matches(event))
+            .find(|(event, starts)| {
+                starts.iter().copied().any(|start| {
+                    let replaced = event.recurrence_id.is_none()
+                        && event.uid.as_deref().is_some_and(|uid| {
+                            self.replaced
+                                .get(uid)
+                                .is_some_and(|starts| starts.contains(&start))
+                        });
+
+                    !replaced && start - self.lead_time <= now && now < start + event.duration
+                })
+            })
+            .map(|(event, _)| event.summary.as_str())
+    }
+
+    fn check_at(&mut self, now: DateTime<Utc>) -> bool {
+        if let Err(err) = self.reload() {
+            // The events loaded before are still used
+            warn!(error = format!("{err:#}"), "Cannot reload calendar");
+        }
+
+        self.expand(now + self.lead_time);
+        let current = self.current_event(now).map(str::to_owned);
+
+        if current != self.current {
+            match &current {
+                Some(summary) => info!(summary, "Calendar event started"),
+                None => info!("Calendar event ended"),
+            }
+            self.current = current;
+        }
+
+        self.current.is_some()
+    }
+}
+
+impl Condition for Calendar {
+    fn name(&self) -> &'static str {
+        "calendar"
+    }
+
+    fn check(&mut self) -> bool {
+        self.check_at(Utc::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M")
+            .unwrap()
+            .and_utc()
+    }
+
+    fn expand(rule: &str, start: &str, horizon: &str) -> Vec<String> {
+        let start = utc(start).naive_utc();
+        Rule::parse(rule, Zone::Utc)
+            .unwrap()
+            .expand(start, Zone::Utc, utc(horizon))
+            .into_iter()
+            .map(|time| time.format("%a %Y-%m-%d %H:%M").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_rules() {
+        assert_eq!(
+            expand("FREQ=DAILY;COUNT=3", "2026-10-16 09:00", "2027-01-01 00:00"),
+            [
+                "Fri 2026-10-16 09:00",
+                "Sat 2026-10-17 09:00",
+                "Sun 2026-10-18 09:00"
+            ]
+        );
+        assert_eq!(
+            expand(
+                "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,FR;UNTIL=20261031",
+                "2026-10-16 09:00",
+                "2027-01-01 00:00"
+            ),
+            [
+                "Fri 2026-10-16 09:00",
+                "Mon 2026-10-26 09:00",
+                "Fri 2026-10-30 09:00"
+            ]
+        );
+        assert_eq!(
+            expand(
+                "FREQ=MONTHLY;BYDAY=-1FR",
+                "2026-10-01 09:00",
+                "2026-12-31 00:00"
+            ),
+            [
+                "Fri 2026-10-30 09:00",
+                "Fri 2026-11-27 09:00",
+                "Fri 2026-12-25 09:00"
+            ]
+        );
+        assert_eq!(
+            expand(
+                "FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1;COUNT=2",
+                "2026-10-01 09:00",
+                "2027-01-01 00:00"
+            ),
+            ["Fri 2026-10-30 09:00", "Mon 2026-11-30 09:00"]
+        );
+        assert_eq!(
+            expand("FREQ=MONTHLY", "2026-01-31 09:00", "2026-06-01 00:00"),
+            [
+                "Sat 2026-01-31 09:00",
+                "Tue 2026-03-31 09:00",
+                "Sun 2026-05-31 09:00"
+            ]
+        );
+        assert_eq!(
+            expand(
+                "FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=29",
+                "2024-02-29 09:00",
+                "2031-01-01 00:00"
+            ),
+            ["Thu 2024-02-29 09:00", "Tue 2028-02-29 09:00"]
+        );
+        assert_eq!(
+            expand(
+                "FREQ=DAILY;BYDAY=SA,SU",
+                "2026-10-16 09:00",
+                "2026-10-25 12:00"
+            ),
+            [
+                "Sat 2026-10-17 09:00",
+                "Sun 2026-10-18 09:00",
+                "Sat 2026-10-24 09:00",
+                "Sun 2026-10-25 09:00"
+            ]
+        );
+
+        for rule in [
+            "",
+            "INTERVAL=2",
+            "FREQ=HOURLY",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=WEEKLY;BYDAY=XX",
+            "FREQ=WEEKLY;BYWEEKNO=1",
+        ] {
+            assert!(
+                Rule::parse(rule, Zone::Utc).is_err(),
+                "{rule:?} should not be valid"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_duration("PT1H30M").unwrap(), TimeDelta::minutes(90));
+        assert_eq!(parse_duration("P1W2D").unwrap(), TimeDelta::days(9));
+        assert_eq!(parse_duration("-PT15M").unwrap(), TimeDelta::minutes(-15));
+        assert!(parse_duration("1H").is_err());
+        assert!(parse_duration("PT1D").is_err());
+        assert!(parse_duration("P1").is_err());
+
+        assert_eq!(
+            unescape(r"Review\, planning\; Q4\\n"),
+            r"Review, planning; Q4\n"
+        );
+        assert_eq!(unescape(r"Line\nbreak"), "Line\nbreak");
+
+        let zone = Zone::Tz(chrono_tz::Europe::Warsaw);
+        assert_eq!(
+            parse_time("20261016T090000", zone).unwrap(),
+            (utc("2026-10-16 07:00"), false)
+        );
+        assert_eq!(
+            parse_time("20261016T090000Z", zone).unwrap(),
+            (utc("2026-10-16 09:00"), false)
+        );
+        assert_eq!(
+            parse_time("20261016", zone).unwrap(),
+            (utc("2026-10-15 22:00"), true)
+        );
+        assert!(parse_time("2026-10-16", zone).is_err());
+    }
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//StayAwake//Tests//EN\r
+BEGIN:VEVENT\r
+UID:standup@example.com\r
+SUMMARY:Daily standup\r
+CATEGORIES:Meeting,Team\r
+DTSTART;TZID=Europe/Warsaw:20261012T093000\r
+DTEND;TZID=Europe/Warsaw:20261012T094500\r
+RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR\r
+EXDATE;TZID=Europe/Warsaw:20261014T093000\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:standup@example.com\r
+RECURRENCE-ID;TZID=Europe/Warsaw:20261015T093000\r
+SUMMARY:Daily standup (moved)\r
+DTSTART;TZID=Europe/Warsaw:20261015T110000\r
+DURATION:PT15M\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:demo@example.com\r
+SUMMARY:Quarterly demo\\, all hands\r
+CATEGORIES:Presentation\r
+DTSTART:20261016T120000Z\r
+DTEND:20261016T130000Z\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:lunch@example.com\r
+SUMMARY:Lunch\r
+DTSTART:20261016T100000Z\r
+DTEND:20261016T110000Z\r
+TRANSP:TRANSPARENT\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:cancelled@example.com\r
+SUMMARY:Cancelled\r
+DTSTART:20261016T140000Z\r
+DTEND:20261016T150000Z\r
+STATUS:CANCELLED\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn test_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("calendar.ics");
+        fs::write(&path, CALENDAR).unwrap();
+
+        let mut calendar = Calendar::new(&path, Filters::default(), 5).unwrap();
+        let mut current = |time: &str| {
+            calendar.check_at(utc(time));
+            calendar.current.clone()
+        };
+
+        // 09:30 in Warsaw is 07:30 UTC
+        assert_eq!(current("2026-10-13 07:24"), None);
+        assert_eq!(
+            current("2026-10-13 07:25").as_deref(),
+            Some("Daily standup")
+        );
+        assert_eq!(
+            current("2026-10-13 07:44").as_deref(),
+            Some("Daily standup")
+        );
+        assert_eq!(current("2026-10-13 07:45"), None);
+        // Excluded
+        assert_eq!(current("2026-10-14 07:30"), None);
+        // Moved
+        assert_eq!(current("2026-10-15 07:30"), None);
+        assert_eq!(
+            current("2026-10-15 08:55").as_deref(),
+            Some("Daily standup (moved)")
+        );
+        // Not on weekends, and an hour later in winter time
+        assert_eq!(current("2026-10-24 07:30"), None);
+        assert_eq!(
+            current("2026-10-26 08:30").as_deref(),
+            Some("Daily standup")
+        );
+
+        assert_eq!(
+            current("2026-10-16 12:30").as_deref(),
+            Some("Quarterly demo, all hands")
+        );
+        // Free and cancelled
+        assert_eq!(current("2026-10-16 10:30"), None);
+        assert_eq!(current("2026-10-16 14:30"), None);
+    }
+
+    #[test]
+    fn test_expansion_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("calendar.ics");
+        fs::write(&path, CALENDAR).unwrap();
+
+        let mut calendar = Calendar::new(&path, Filters::default(), 5).unwrap();
+        let window = TimeDelta::days(EXPANSION_DAYS);
+
+        assert!(calendar.check_at(utc("2026-10-13 07:30")));
+        assert_eq!(
+            calendar.expanded_until,
+            Some(utc("2026-10-13 07:35") + window)
+        );
+
+        // Rules are not expanded again while the horizon stays within the window
+        assert!(calendar.check_at(utc("2026-10-19 07:30")));
+        assert!(!calendar.check_at(utc("2026-10-13 08:30")));
+        assert_eq!(
+            calendar.expanded_until,
+            Some(utc("2026-10-13 07:35") + window)
+        );
+
+        assert!(calendar.check_at(utc("2026-10-26 08:30")));
+        assert_eq!(
+            calendar.expanded_until,
+            Some(utc("2026-10-26 08:35") + window)
+        );
+
+        // Nor until the file changes
+        fs::write(&path, CALENDAR.replace("BYDAY=MO,TU,WE,TH,FR", "BYDAY=MO")).unwrap();
+        assert!(!calendar.check_at(utc("2026-10-27 08:30")));
+        assert_eq!(
+            calendar.expanded_until,
+            Some(utc("2026-10-27 08:35") + window)
+        );
+    }
+
+    #[test]
+    fn test_filters_and_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("calendar.ics");
+        fs::write(&path, CALENDAR).unwrap();
+
+        let filters = Filters {
+            summary: None,
+            categories: vec!["presentation".to_owned()],
+        };
+        let mut calendar = Calendar::new(&path, filters, 0).unwrap();
+        assert!(!calendar.check_at(utc("2026-10-13 07:30")));
+        assert!(calendar.check_at(utc("2026-10-16 12:30")));
+
+        let filters = Filters {
+            summary: Some(Regex::new("(?i)standup").unwrap()),
+            categories: Vec::new(),
+        };
+        let mut calendar = Calendar::new(&path, filters, 0).unwrap();
+        assert!(calendar.check_at(utc("2026-10-13 07:30")));
+        assert!(!calendar.check_at(utc("2026-10-16 12:30")));
+
+        fs::write(&path, CALENDAR.replace("Daily standup", "Daily sync")).unwrap();
+        assert!(!calendar.check_at(utc("2026-10-13 07:30")));
+
+        fs::write(&path, CALENDAR).unwrap();
+        assert!(calendar.check_at(utc("2026-10-13 07:30")));
+
+        // The events loaded before are kept
+        fs::write(&path, "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\n").unwrap();
+        assert!(calendar.check_at(utc("2026-10-13 07:30")));
+        fs::remove_file(&path).unwrap();
+        assert!(calendar.check_at(utc("2026-10-13 07:30")));
+
+        assert!(Calendar::new(&path, Filters::default(), 0).is_err());
+    }
+}
@@ -0,0 +1,63 @@
+//! Keeping the system awake while a sentinel file exists (`KEEP_AWAKE_WHILE_FILE_EXISTS`)
+
+use std::path::{Path, PathBuf};
+
+use tracing::info;
+
+use super::Condition;
+
+#[derive(Debug)]
+pub(crate) struct FileExists {
+    path: PathBuf,
+    exists: bool,
+}
+
+impl FileExists {
+    pub(crate) fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            exists: false,
+        }
+    }
+}
+
+impl Condition for FileExists {
+    fn name(&self) -> &'static str {
+        "file-exists"
+    }
+
+    fn check(&mut self) -> bool {
+        let exists = self.path.exists();
+
+        if exists != self.exists {
+            if exists {
+                info!(path = %self.path.display(), "Sentinel file created");
+            } else {
+                info!(path = %self.path.display(), "Sentinel file removed");
+            }
+            self.exists = exists;
+        }
+
+        exists
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sentinel() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stayawake.lock");
+        let mut file_exists = FileExists::new(&path);
+
+        assert!(!file_exists.check());
+
+        std::fs::write(&path, "").unwrap();
+        assert!(file_exists.check());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(!file_exists.check());
+    }
+}
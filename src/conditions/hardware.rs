@@ -0,0 +1,225 @@
+//! Keeping the system awake while some hardware is connected, e.g. when the laptop is docked
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use tracing::info;
+
+use super::Condition;
+use crate::models::UsbId;
+
+/// Connectors of built-in displays
+const INTERNAL_CONNECTORS: &[&str] = &["eDP", "LVDS", "DSI"];
+
+/// Names of the entries of a sysfs directory, empty if it does not exist
+fn entries(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    names
+}
+
+fn read_attribute(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|value| value.trim().to_owned())
+}
+
+/// External monitor connected to one of the `/sys/class/drm/card*-*` connectors
+#[derive(Debug)]
+pub(crate) struct ExternalMonitor {
+    sysfs_root: PathBuf,
+    connected: BTreeSet<String>,
+}
+
+impl ExternalMonitor {
+    pub(crate) fn new(sysfs_root: &Path) -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            anyhow::bail!("External monitors can only be detected on Linux");
+        }
+
+        Ok(Self {
+            sysfs_root: sysfs_root.to_owned(),
+            connected: BTreeSet::new(),
+        })
+    }
+
+    fn scan(&self) -> BTreeSet<String> {
+        let drm = self.sysfs_root.join("class/drm");
+
+        entries(&drm)
+            .into_iter()
+            .filter(|name| {
+                // Connectors are named after their card, e.g. card0-HDMI-A-1
+                let Some((_card, connector)) = name.split_once('-') else {
+                    return false;
+                };
+
+                !INTERNAL_CONNECTORS
+                    .iter()
+                    .any(|internal| connector.starts_with(internal))
+            })
+            .filter(|name| {
+                read_attribute(&drm.join(name).join("status")).as_deref() == Some("connected")
+            })
+            .collect()
+    }
+}
+
+impl Condition for ExternalMonitor {
+    fn name(&self) -> &'static str {
+        "external-monitor"
+    }
+
+    fn check(&mut self) -> bool {
+        let connected = self.scan();
+
+        for connector in connected.difference(&self.connected) {
+            info!(connector, "External monitor connected");
+        }
+
+        for connector in self.connected.difference(&connected) {
+            info!(connector, "External monitor disconnected");
+        }
+
+        self.connected = connected;
+        !self.connected.is_empty()
+    }
+}
+
+/// One of the configured devices connected to a USB port (`/sys/bus/usb/devices`)
+#[derive(Debug)]
+pub(crate) struct UsbDevice {
+    sysfs_root: PathBuf,
+    devices: Vec<UsbId>,
+    connected: BTreeSet<UsbId>,
+}
+
+impl UsbDevice {
+    pub(crate) fn new(sysfs_root: &Path, devices: &[UsbId]) -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            anyhow::bail!("USB devices can only be detected on Linux");
+        }
+
+        Ok(Self {
+            sysfs_root: sysfs_root.to_owned(),
+            devices: devices.to_vec(),
+            connected: BTreeSet::new(),
+        })
+    }
+
+    fn scan(&self) -> BTreeSet<UsbId> {
+        let usb_devices = self.sysfs_root.join("bus/usb/devices");
+
+        entries(&usb_devices)
+            .into_iter()
+            .filter_map(|name| {
+                let device = usb_devices.join(name);
+                let vendor = read_attribute(&device.join("idVendor"))?;
+                let product = read_attribute(&device.join("idProduct"))?;
+
+                Some(UsbId {
+                    vendor: u16::from_str_radix(&vendor, 16).ok()?,
+                    product: u16::from_str_radix(&product, 16).ok()?,
+                })
+            })
+            .filter(|id| self.devices.contains(id))
+            .collect()
+    }
+}
+
+impl Condition for UsbDevice {
+    fn name(&self) -> &'static str {
+        "usb-device"
+    }
+
+    fn check(&mut self) -> bool {
+        let connected = self.scan();
+
+        for device in connected.difference(&self.connected) {
+            info!(%device, "USB device connected");
+        }
+
+        for device in self.connected.difference(&connected) {
+            info!(%device, "USB device disconnected");
+        }
+
+        self.connected = connected;
+        !self.connected.is_empty()
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    fn write(root: &Path, path: &str, value: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, format!("{value}\n")).unwrap();
+    }
+
+    #[test]
+    fn test_external_monitor() {
+        let root = tempfile::tempdir().unwrap();
+        write(root.path(), "class/drm/card0-eDP-1/status", "connected");
+        write(
+            root.path(),
+            "class/drm/card0-HDMI-A-1/status",
+            "disconnected",
+        );
+        write(root.path(), "class/drm/card0-DP-1/status", "disconnected");
+        write(root.path(), "class/drm/version", "drm 1.1.0 20060810");
+
+        let mut monitor = ExternalMonitor::new(root.path()).unwrap();
+        // The built-in display does not count
+        assert!(!monitor.check());
+
+        write(root.path(), "class/drm/card0-DP-1/status", "connected");
+        assert!(monitor.check());
+        assert_eq!(monitor.connected, BTreeSet::from(["card0-DP-1".to_owned()]));
+
+        write(root.path(), "class/drm/card0-DP-1/status", "disconnected");
+        assert!(!monitor.check());
+    }
+
+    #[test]
+    fn test_usb_device() {
+        let root = tempfile::tempdir().unwrap();
+        write(root.path(), "bus/usb/devices/1-1/idVendor", "046d");
+        write(root.path(), "bus/usb/devices/1-1/idProduct", "c52b");
+        write(root.path(), "bus/usb/devices/1-1:1.0/bInterfaceClass", "03");
+
+        let dock = UsbId {
+            vendor: 0x17ef,
+            product: 0xa396,
+        };
+        let mut usb = UsbDevice::new(root.path(), &[dock]).unwrap();
+        assert!(!usb.check());
+
+        write(root.path(), "bus/usb/devices/2-1/idVendor", "17ef");
+        write(root.path(), "bus/usb/devices/2-1/idProduct", "a396");
+        assert!(usb.check());
+        assert_eq!(usb.connected, BTreeSet::from([dock]));
+
+        fs::remove_dir_all(root.path().join("bus/usb/devices/2-1")).unwrap();
+        assert!(!usb.check());
+    }
+
+    #[test]
+    fn test_missing_sysfs() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(!ExternalMonitor::new(root.path()).unwrap().check());
+        assert!(!UsbDevice::new(root.path(), &[]).unwrap().check());
+    }
+}
@@ -0,0 +1,277 @@
+//! Conditions deciding whether the system should be kept awake right now
+
+use std::{
+    fmt::Debug,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_with::{serde_as, DurationSeconds};
+use tracing::trace;
+
+use crate::{config::Config, models::Mode};
+
+pub(crate) mod activity;
+pub(crate) mod calendar;
+pub(crate) mod file_exists;
+pub(crate) mod hardware;
+// Linux-only conditions are built everywhere and fail to start elsewhere, except this one needing inotify
+#[cfg(target_os = "linux")]
+pub(crate) mod path_changes;
+pub(crate) mod plugin;
+pub(crate) mod power_supply;
+pub(crate) mod process;
+pub(crate) mod schedule;
+pub(crate) mod tcp;
+pub(crate) mod time;
+
+/// Something that keeps the system awake while it holds
+pub(crate) trait Condition: Send + Debug {
+    /// Short name used in logs
+    fn name(&self) -> &'static str;
+
+    /// Checks the condition, called on every tick of the main loop
+    fn check(&mut self) -> bool;
+
+    /// Settings the condition overrides as of its last check
+    fn overrides(&self) -> Overrides {
+        Overrides::default()
+    }
+
+    /// While the condition doesn't hold, the earliest time it can hold again, `None` if it can at any time
+    fn holds_again_at(&self) -> Option<Instant> {
+        None
+    }
+
+    /// Whether the result of the first check is not known yet, e.g. while a command runs in the background
+    fn pending(&self) -> bool {
+        false
+    }
+}
+
+/// Check interval and mode requested by a condition instead of the configured ones
+#[serde_as]
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct Overrides {
+    #[serde_as(as = "Option<DurationSeconds<u64>>")]
+    #[serde(default)]
+    pub(crate) interval: Option<Duration>,
+    #[serde(default)]
+    pub(crate) mode: Option<Mode>,
+}
+
+impl Overrides {
+    /// Combines two overrides, the settings of `self` take precedence
+    pub(crate) fn or(self, other: Self) -> Self {
+        Self {
+            interval: self.interval.or(other.interval),
+            mode: self.mode.or(other.mode),
+        }
+    }
+}
+
+/// Configured conditions, the system is kept awake while all the requirements and any of the triggers hold
+#[derive(Debug, Default)]
+pub(crate) struct Conditions {
+    triggers: Vec<Box<dyn Condition>>,
+    requirements: Vec<Box<dyn Condition>>,
+}
+
+impl Conditions {
+    pub(crate) fn from_config(config: &Config) -> Result<Self> {
+        let mut conditions = Self::default();
+
+        if !config.keep_awake_while_process.is_empty() {
+            conditions.add(process::ProcessRunning::new(
+                &config.keep_awake_while_process,
+            )?);
+        }
+
+        let thresholds = activity::Thresholds {
+            cpu_percent: config.cpu_busy_percent,
+            disk_kbps: config.disk_busy_kbps,
+            net_kbps: config.net_busy_kbps,
+        };
+        if thresholds != activity::Thresholds::default() {
+            conditions.add(activity::Activity::new(
+                thresholds,
+                config.activity_quiet_period,
+            )?);
+        }
+
+        if config.keep_awake_while_tcp_sessions {
+            conditions.add(tcp::TcpSessions::new(&config.tcp_session_ports)?);
+        }
+
+        if let Some(path) = &config.keep_awake_while_path_changes {
+            #[cfg(target_os = "linux")]
+            conditions.add(path_changes::PathChanges::new(
+                path,
+                config.path_changes_quiet_period,
+            )?);
+
+            #[cfg(not(target_os = "linux"))]
+            anyhow::bail!(
+                "KEEP_AWAKE_WHILE_PATH_CHANGES is only supported on Linux (cannot watch {})",
+                path.display()
+            );
+        }
+
+        if let Some(path) = &config.keep_awake_while_file_exists {
+            conditions.add(file_exists::FileExists::new(path));
+        }
+
+        if config.keep_awake_while_external_monitor {
+            conditions.add(hardware::ExternalMonitor::new(&config.sysfs_root)?);
+        }
+
+        if !config.keep_awake_while_usb_device.is_empty() {
+            conditions.add(hardware::UsbDevice::new(
+                &config.sysfs_root,
+                &config.keep_awake_while_usb_device,
+            )?);
+        }
+
+        if let Some(path) = &config.calendar_ics {
+            conditions.add(calendar::Calendar::new(
+                path,
+                config.calendar_filters(),
+                config.calendar_minutes_before,
+            )?);
+        }
+
+        for command in &config.plugins {
+            conditions.add(plugin::Plugin::new(
+                command,
+                config.plugin_interval,
+                config.plugin_timeout,
+            ));
+        }
+
+        if let Some(schedule) = &config.schedule {
+            conditions.require(schedule::InSchedule::new(
+                schedule.clone(),
+                config.schedule_timezone,
+            ));
+        }
+
+        if config.only_on_ac || config.release_below_battery_percent.is_some() {
+            conditions.require(power_supply::PowerSupply::new(
+                &config.sysfs_root,
+                config.only_on_ac,
+                config.release_below_battery_percent,
+            )?);
+        }
+
+        Ok(conditions)
+    }
+
+    fn add(&mut self, trigger: impl Condition + 'static) {
+        self.triggers.push(Box::new(trigger));
+    }
+
+    fn require(&mut self, requirement: impl Condition + 'static) {
+        self.requirements.push(Box::new(requirement));
+    }
+}
+
+impl Condition for Conditions {
+    fn name(&self) -> &'static str {
+        "conditions"
+    }
+
+    /// Whether the system should be kept awake, always true if there are no conditions
+    fn check(&mut self) -> bool {
+        // Every condition is checked, so that all of them can log their own changes
+        let check = |condition: &mut Box<dyn Condition>| {
+            let holds = condition.check();
+            trace!(condition = condition.name(), holds, "Condition checked");
+            holds
+        };
+
+        let allowed = self
+            .requirements
+            .iter_mut()
+            .fold(true, |allowed, requirement| check(requirement) & allowed);
+
+        let triggered = self.triggers.is_empty()
+            || self
+                .triggers
+                .iter_mut()
+                .fold(false, |triggered, trigger| check(trigger) | triggered);
+
+        allowed && triggered
+    }
+
+    fn overrides(&self) -> Overrides {
+        self.requirements
+            .iter()
+            .chain(&self.triggers)
+            .fold(Overrides::default(), |overrides, condition| {
+                overrides.or(condition.overrides())
+            })
+    }
+
+    fn holds_again_at(&self) -> Option<Instant> {
+        // All the requirements have to hold, which doesn't happen before the last of them holds again
+        self.requirements
+            .iter()
+            .filter_map(|requirement| requirement.holds_again_at())
+            .max()
+    }
+
+    fn pending(&self) -> bool {
+        self.requirements
+            .iter()
+            .chain(&self.triggers)
+            .any(|condition| condition.pending())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Fixed(bool);
+
+    impl Condition for Fixed {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        fn check(&mut self) -> bool {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_any_holds() {
+        let mut conditions = Conditions::default();
+        assert!(conditions.check());
+
+        conditions.add(Fixed(false));
+        assert!(!conditions.check());
+
+        conditions.add(Fixed(true));
+        conditions.add(Fixed(false));
+        assert!(conditions.check());
+    }
+
+    #[test]
+    fn test_all_requirements_hold() {
+        let mut conditions = Conditions::default();
+        conditions.require(Fixed(true));
+        assert!(conditions.check());
+
+        conditions.add(Fixed(false));
+        assert!(!conditions.check());
+
+        conditions.add(Fixed(true));
+        assert!(conditions.check());
+
+        conditions.require(Fixed(false));
+        assert!(!conditions.check());
+    }
+}
@@ -0,0 +1,219 @@
+//! Keeping the system awake while files in a directory change (`KEEP_AWAKE_WHILE_PATH_CHANGES`)
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use tracing::{debug, info, warn};
+
+use super::Condition;
+
+/// Events meaning that something was written, created, removed or renamed
+const CHANGES: WatchMask = WatchMask::MODIFY
+    .union(WatchMask::ATTRIB)
+    .union(WatchMask::CLOSE_WRITE)
+    .union(WatchMask::CREATE)
+    .union(WatchMask::DELETE)
+    .union(WatchMask::MOVED_FROM)
+    .union(WatchMask::MOVED_TO);
+
+pub(crate) struct PathChanges {
+    root: PathBuf,
+    /// How long the system stays awake after the last change
+    quiet_period: Duration,
+    inotify: Inotify,
+    /// Watched directories, the root and all its subdirectories
+    watches: HashMap<WatchDescriptor, PathBuf>,
+    last_change: Option<Instant>,
+    holds: bool,
+}
+
+impl std::fmt::Debug for PathChanges {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PathChanges")
+            .field("root", &self.root)
+            .field("quiet_period", &self.quiet_period)
+            .field("watches", &self.watches.len())
+            .field("last_change", &self.last_change)
+            .finish()
+    }
+}
+
+impl PathChanges {
+    pub(crate) fn new(root: &Path, quiet_period: Duration) -> Result<Self> {
+        let inotify = Inotify::init().context("Cannot initialize inotify")?;
+
+        let mut path_changes = Self {
+            root: root.to_owned(),
+            quiet_period,
+            inotify,
+            watches: HashMap::new(),
+            last_change: None,
+            holds: false,
+        };
+
+        path_changes
+            .watch_tree(root)
+            .with_context(|| format!("Cannot watch {}", root.display()))?;
+
+        info!(path = %root.display(), directories = path_changes.watches.len(), "Watching for changes");
+
+        Ok(path_changes)
+    }
+
+    /// Watches `dir` and all directories below it
+    fn watch_tree(&mut self, dir: &Path) -> io::Result<()> {
+        let wd = self
+            .inotify
+            .watches()
+            .add(dir, CHANGES | WatchMask::ONLYDIR)?;
+        self.watches.insert(wd, dir.to_owned());
+
+        for entry in fs::read_dir(dir)?.flatten() {
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                // Subdirectories may disappear in the meantime
+                if let Err(err) = self.watch_tree(&entry.path()) {
+                    debug!(path = %entry.path().display(), error = %err, "Cannot watch directory");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads all pending events, returns whether any of them was a change
+    fn read_changes(&mut self) -> bool {
+        let mut buffer = [0; 4096];
+        let mut changed = false;
+
+        loop {
+            let events = match self.inotify.read_events(&mut buffer) {
+                Ok(events) => events,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    warn!(error = %err, "Cannot read inotify events");
+                    break;
+                }
+            };
+
+            let mut new_dirs = Vec::new();
+            let mut read_any = false;
+
+            for event in events {
+                read_any = true;
+
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    changed = true;
+                    continue;
+                }
+
+                if event.mask.contains(EventMask::IGNORED) {
+                    self.watches.remove(&event.wd);
+                    continue;
+                }
+
+                changed = true;
+
+                if event.mask.contains(EventMask::ISDIR)
+                    && event
+                        .mask
+                        .intersects(EventMask::CREATE | EventMask::MOVED_TO)
+                {
+                    if let (Some(parent), Some(name)) = (self.watches.get(&event.wd), event.name) {
+                        new_dirs.push(parent.join(name));
+                    }
+                }
+            }
+
+            for dir in new_dirs {
+                if let Err(err) = self.watch_tree(&dir) {
+                    debug!(path = %dir.display(), error = %err, "Cannot watch directory");
+                }
+            }
+
+            if !read_any {
+                break;
+            }
+        }
+
+        changed
+    }
+
+    fn check_at(&mut self, now: Instant) -> bool {
+        if self.read_changes() {
+            self.last_change = Some(now);
+        }
+
+        let holds = self
+            .last_change
+            .is_some_and(|last_change| now - last_change < self.quiet_period);
+
+        if holds != self.holds {
+            if holds {
+                info!(path = %self.root.display(), "Files are changing");
+            } else {
+                info!(path = %self.root.display(), quiet_period = ?self.quiet_period, "Files have not changed for the whole quiet period");
+            }
+            self.holds = holds;
+        }
+
+        holds
+    }
+}
+
+impl Condition for PathChanges {
+    fn name(&self) -> &'static str {
+        "path-changes"
+    }
+
+    fn check(&mut self) -> bool {
+        self.check_at(Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("existing")).unwrap();
+
+        let mut path_changes = PathChanges::new(root.path(), Duration::from_secs(60)).unwrap();
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+
+        assert!(!path_changes.check_at(at(0)));
+
+        fs::write(root.path().join("download.part"), "data").unwrap();
+        assert!(path_changes.check_at(at(10)));
+        assert!(path_changes.check_at(at(69)));
+        assert!(!path_changes.check_at(at(70)));
+
+        // Existing subdirectories are watched too
+        fs::write(root.path().join("existing/file"), "data").unwrap();
+        assert!(path_changes.check_at(at(80)));
+        assert!(!path_changes.check_at(at(140)));
+
+        // As well as the ones created later
+        fs::create_dir(root.path().join("new")).unwrap();
+        assert!(path_changes.check_at(at(150)));
+        fs::write(root.path().join("new/file"), "data").unwrap();
+        assert!(path_changes.check_at(at(200)));
+        assert!(path_changes.check_at(at(259)));
+        assert!(!path_changes.check_at(at(260)));
+    }
+
+    #[test]
+    fn test_missing_directory() {
+        let root = tempfile::tempdir().unwrap();
+        let result = PathChanges::new(&root.path().join("missing"), Duration::from_secs(60));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,382 @@
+//! External commands deciding whether to keep the system awake (`PLUGINS`)
+//!
+//! A plugin holds while its command exits with code 0. The last line of its standard output can be a JSON object
+//! overriding the check interval and the mode while it holds, e.g. `{"interval": 60, "mode": "lock-only"}`.
+
+use std::{
+    io::Read,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tracing::{debug, info, warn};
+
+use super::{Condition, Overrides};
+
+/// How often a running command is checked for having exited
+const POLL_PERIOD: Duration = Duration::from_millis(20);
+
+/// Result of a single run of the command
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Report {
+    holds: bool,
+    overrides: Overrides,
+}
+
+#[derive(Debug)]
+pub(crate) struct Plugin {
+    command: String,
+    period: Duration,
+    timeout: Duration,
+    last_run: Option<Instant>,
+    /// Result of the run in progress
+    running: Option<Receiver<Report>>,
+    /// Result of the last finished run
+    report: Option<Report>,
+}
+
+impl Plugin {
+    /// The command is run by the shell every `period`, and killed if it does not exit within `timeout`
+    pub(crate) fn new(command: &str, period: Duration, timeout: Duration) -> Self {
+        Self {
+            command: command.to_owned(),
+            period,
+            timeout,
+            last_run: None,
+            running: None,
+            report: None,
+        }
+    }
+
+    fn check_at(&mut self, now: Instant) -> bool {
+        if let Some(running) = &self.running {
+            match running.try_recv() {
+                Ok(report) => self.finish(report),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.finish(Report::default()),
+            }
+        }
+
+        let due = match self.last_run {
+            Some(last_run) => now.duration_since(last_run) >= self.period,
+            None => true,
+        };
+
+        if self.running.is_none() && due {
+            self.last_run = Some(now);
+
+            let (sender, receiver) = mpsc::channel();
+            let command = self.command.clone();
+            let timeout = self.timeout;
+            thread::spawn(move || {
+                let _ = sender.send(run(&command, timeout));
+            });
+
+            self.running = Some(receiver);
+        }
+
+        // Doesn't hold until the first run finished
+        self.report.is_some_and(|report| report.holds)
+    }
+
+    fn finish(&mut self, report: Report) {
+        let holds = report.holds;
+
+        if self.report.map(|report| report.holds) != Some(holds) {
+            info!(plugin = self.command, holds, "Plugin result changed");
+        }
+
+        self.running = None;
+        self.report = Some(report);
+    }
+}
+
+impl Condition for Plugin {
+    fn name(&self) -> &'static str {
+        "plugin"
+    }
+
+    fn check(&mut self) -> bool {
+        self.check_at(Instant::now())
+    }
+
+    fn overrides(&self) -> Overrides {
+        match self.report {
+            Some(report) if report.holds => report.overrides,
+            _ => Overrides::default(),
+        }
+    }
+
+    fn pending(&self) -> bool {
+        self.report.is_none()
+    }
+}
+
+/// Runs the command once, waiting at most `timeout` for it to exit
+fn run(command: &str, timeout: Duration) -> Report {
+    let mut child = match shell(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => {
+            warn!(plugin = command, error = %err, "Cannot start plugin");
+            return Report::default();
+        }
+    };
+
+    // Both pipes are drained while the command runs, so that it never blocks on a full pipe
+    let stdout = child.stdout.take().map(read_all);
+    let stderr = child.stderr.take().map(read_all);
+
+    let status = wait(&mut child, timeout);
+
+    let stdout = stdout
+        .and_then(|reader| reader.join().ok())
+        .unwrap_or_default();
+    let stderr = stderr
+        .and_then(|reader| reader.join().ok())
+        .unwrap_or_default();
+
+    for line in stderr.lines().filter(|line| !line.trim().is_empty()) {
+        debug!(plugin = command, line, "Plugin error output");
+    }
+
+    let mut overrides = Overrides::default();
+    let mut lines = stdout
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .peekable();
+
+    while let Some(line) = lines.next() {
+        if lines.peek().is_none() && line.trim_start().starts_with('{') {
+            match parse_overrides(line) {
+                Ok(parsed) => overrides = parsed,
+                Err(err) => warn!(plugin = command, line, error = %err, "Invalid plugin output"),
+            }
+        } else {
+            debug!(plugin = command, line, "Plugin output");
+        }
+    }
+
+    match status {
+        Some(status) => {
+            debug!(plugin = command, %status, ?overrides, "Plugin exited");
+            Report {
+                holds: status.success(),
+                overrides,
+            }
+        }
+        None => {
+            warn!(
+                plugin = command,
+                ?timeout,
+                "Plugin timed out and was killed"
+            );
+            Report::default()
+        }
+    }
+}
+
+fn parse_overrides(line: &str) -> anyhow::Result<Overrides> {
+    let overrides: Overrides = serde_json::from_str(line)?;
+
+    if overrides.interval == Some(Duration::ZERO) {
+        anyhow::bail!("interval cannot be equal to zero");
+    }
+
+    Ok(overrides)
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    use std::os::unix::process::CommandExt;
+
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    // Own process group, so that whatever the command started is killed along with it
+    shell.process_group(0);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+/// Waits for the command to exit, returns `None` if it didn't exit within `timeout`
+fn wait(child: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+    let deadline = Instant::now() + timeout;
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if Instant::now() < deadline => thread::sleep(POLL_PERIOD),
+            Ok(None) => break None,
+            Err(err) => {
+                warn!(error = %err, "Cannot wait for plugin");
+                break None;
+            }
+        }
+    };
+
+    // Whatever the command left running in the background would also keep its output open
+    kill(child);
+    if status.is_none() {
+        let _ = child.wait();
+    }
+
+    status
+}
+
+#[cfg(unix)]
+fn kill(child: &mut Child) {
+    // The process group has the same ID as the shell
+    unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+}
+
+#[cfg(not(unix))]
+fn kill(child: &mut Child) {
+    // Killing the shell alone would leave the command running, `/T` kills the whole tree
+    let killed = Command::new("taskkill")
+        .args(["/T", "/F", "/PID"])
+        .arg(child.id().to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+
+    if !killed {
+        let _ = child.kill();
+    }
+}
+
+fn read_all(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = pipe.read_to_end(&mut output);
+        String::from_utf8_lossy(&output).into_owned()
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::models::Mode;
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_exit_code() {
+        assert!(run("true", TIMEOUT).holds);
+        assert!(!run("echo busy; exit 3", TIMEOUT).holds);
+        assert!(!run("/stayawake/does/not/exist", TIMEOUT).holds);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let report = run(
+            r#"echo checking; echo '{"interval": 60, "mode": "lock-only", "note": "VPN up"}'"#,
+            TIMEOUT,
+        );
+        assert_eq!(
+            report,
+            Report {
+                holds: true,
+                overrides: Overrides {
+                    interval: Some(Duration::from_secs(60)),
+                    mode: Some(Mode::LockOnly),
+                },
+            }
+        );
+
+        // Only the last line is parsed
+        let report = run(r#"echo '{"interval": 60}'; echo done"#, TIMEOUT);
+        assert_eq!(report.overrides, Overrides::default());
+
+        for output in [r#"{"mode": "sleep"}"#, r#"{"interval": 0}"#, "{"] {
+            let report = run(&format!("echo '{output}'"), TIMEOUT);
+            assert!(report.holds);
+            assert_eq!(report.overrides, Overrides::default(), "{output}");
+        }
+
+        let mut plugin = Plugin::new(r#"echo '{"mode": "both"}'; exit 1"#, TIMEOUT, TIMEOUT);
+        let start = Instant::now();
+        assert!(!plugin.check_at(start));
+        finish_first_run(&mut plugin, start);
+        assert!(!plugin.check_at(start));
+        assert_eq!(plugin.overrides(), Overrides::default());
+    }
+
+    /// Checks until the result of the first run is known, panics after a few seconds
+    fn finish_first_run(plugin: &mut Plugin, now: Instant) {
+        let deadline = Instant::now() + TIMEOUT;
+        while plugin.report.is_none() {
+            assert!(Instant::now() < deadline, "plugin did not finish");
+            plugin.check_at(now);
+            thread::sleep(POLL_PERIOD);
+        }
+    }
+
+    #[test]
+    fn test_timeout() {
+        let start = Instant::now();
+        // The background process keeps the pipes open unless it is killed as well
+        assert!(!run("sleep 10 & sleep 10", Duration::from_millis(200)).holds);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let start = Instant::now();
+        assert!(run("sleep 10 & exit 0", TIMEOUT).holds);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_schedule() {
+        let dir = tempfile::tempdir().unwrap();
+        let runs = dir.path().join("runs");
+        let ready = dir.path().join("ready");
+        let command = format!(
+            "echo run >> {}; test -e {}",
+            runs.display(),
+            ready.display()
+        );
+        let count = || std::fs::read_to_string(&runs).unwrap().lines().count();
+
+        let period = Duration::from_secs(60);
+        let mut plugin = Plugin::new(&command, period, TIMEOUT);
+        let start = Instant::now();
+
+        // Nothing holds before the first run finishes, a slow command doesn't hold up the check
+        let slow = Plugin::new("sleep 10", period, TIMEOUT).check_at(start);
+        assert!(!slow);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        assert!(!plugin.check_at(start));
+        assert!(plugin.pending());
+        finish_first_run(&mut plugin, start);
+        assert!(!plugin.pending());
+        assert!(!plugin.check_at(start));
+        assert_eq!(count(), 1);
+
+        std::fs::write(&ready, "").unwrap();
+        assert!(!plugin.check_at(start + period / 2));
+        assert_eq!(count(), 1);
+
+        // Later runs are in the background as well, their result is used by the following checks
+        plugin.check_at(start + period);
+        let deadline = Instant::now() + TIMEOUT;
+        while !plugin.check_at(start + period) {
+            assert!(Instant::now() < deadline, "plugin did not finish");
+            thread::sleep(POLL_PERIOD);
+        }
+        assert_eq!(count(), 2);
+    }
+}
@@ -0,0 +1,221 @@
+//! Not keeping the system awake on battery (`ONLY_ON_AC`, `RELEASE_BELOW_BATTERY_PERCENT`)
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use tracing::info;
+
+use super::Condition;
+
+/// State of the power supplies in `/sys/class/power_supply`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Status {
+    /// Whether the system is powered by an external source, always true without a battery
+    on_ac: bool,
+    /// Average charge of the system batteries
+    battery_percent: Option<u8>,
+}
+
+#[derive(Debug)]
+pub(crate) struct PowerSupply {
+    sysfs_root: PathBuf,
+    only_on_ac: bool,
+    release_below_battery_percent: Option<u8>,
+    /// Result of the previous check
+    holds: Option<bool>,
+}
+
+impl PowerSupply {
+    pub(crate) fn new(
+        sysfs_root: &Path,
+        only_on_ac: bool,
+        release_below_battery_percent: Option<u8>,
+    ) -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            anyhow::bail!(
+                "ONLY_ON_AC and RELEASE_BELOW_BATTERY_PERCENT are only supported on Linux"
+            );
+        }
+
+        Ok(Self {
+            sysfs_root: sysfs_root.to_owned(),
+            only_on_ac,
+            release_below_battery_percent,
+            holds: None,
+        })
+    }
+
+    fn status(&self) -> Status {
+        let class = self.sysfs_root.join("class/power_supply");
+        let read = |supply: &Path, attribute: &str| {
+            fs::read_to_string(supply.join(attribute))
+                .map(|value| value.trim().to_owned())
+                .unwrap_or_default()
+        };
+
+        let mut external_online = false;
+        let mut batteries = Vec::new();
+
+        for entry in fs::read_dir(&class).into_iter().flatten().flatten() {
+            let supply = entry.path();
+
+            match read(&supply, "type").as_str() {
+                // Batteries of wireless mice and keyboards have the "Device" scope
+                "Battery" if read(&supply, "scope") != "Device" => {
+                    if let Ok(capacity) = read(&supply, "capacity").parse::<u8>() {
+                        batteries.push(capacity);
+                    }
+                }
+                "Battery" => {}
+                _ => external_online |= read(&supply, "online") == "1",
+            }
+        }
+
+        let battery_percent = (!batteries.is_empty()).then(|| {
+            let total: u32 = batteries.iter().copied().map(u32::from).sum();
+            (total / batteries.len() as u32) as u8
+        });
+
+        Status {
+            on_ac: external_online || battery_percent.is_none(),
+            battery_percent,
+        }
+    }
+
+    fn allows(&self, status: &Status) -> bool {
+        if self.only_on_ac && !status.on_ac {
+            return false;
+        }
+
+        // A charging battery will not run flat
+        match (self.release_below_battery_percent, status.battery_percent) {
+            (Some(threshold), Some(percent)) if !status.on_ac => percent >= threshold,
+            _ => true,
+        }
+    }
+}
+
+impl Condition for PowerSupply {
+    fn name(&self) -> &'static str {
+        "power-supply"
+    }
+
+    fn check(&mut self) -> bool {
+        let status = self.status();
+        let holds = self.allows(&status);
+
+        if self.holds != Some(holds) {
+            if holds {
+                info!(
+                    on_ac = status.on_ac,
+                    battery_percent = status.battery_percent,
+                    "Power source allows keeping the system awake"
+                );
+            } else {
+                info!(
+                    on_ac = status.on_ac,
+                    battery_percent = status.battery_percent,
+                    "Power source does not allow keeping the system awake"
+                );
+            }
+            self.holds = Some(holds);
+        }
+
+        holds
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    fn supply(root: &Path, name: &str, attributes: &[(&str, &str)]) {
+        let dir = root.join("class/power_supply").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        for (attribute, value) in attributes {
+            fs::write(dir.join(attribute), format!("{value}\n")).unwrap();
+        }
+    }
+
+    fn laptop(root: &Path, online: &str, capacity: &str) {
+        supply(root, "AC", &[("type", "Mains"), ("online", online)]);
+        supply(
+            root,
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("scope", "System"),
+                ("capacity", capacity),
+            ],
+        );
+        // A nearly empty wireless mouse should not matter
+        supply(
+            root,
+            "hidpp_battery_0",
+            &[("type", "Battery"), ("scope", "Device"), ("capacity", "5")],
+        );
+    }
+
+    #[test]
+    fn test_status() {
+        let root = tempfile::tempdir().unwrap();
+        let power_supply = PowerSupply::new(root.path(), true, None).unwrap();
+
+        // Desktops have no battery at all
+        assert_eq!(
+            power_supply.status(),
+            Status {
+                on_ac: true,
+                battery_percent: None
+            }
+        );
+
+        laptop(root.path(), "0", "80");
+        supply(
+            root.path(),
+            "BAT1",
+            &[("type", "Battery"), ("capacity", "40")],
+        );
+        assert_eq!(
+            power_supply.status(),
+            Status {
+                on_ac: false,
+                battery_percent: Some(60)
+            }
+        );
+    }
+
+    #[test]
+    fn test_only_on_ac() {
+        let root = tempfile::tempdir().unwrap();
+        let mut power_supply = PowerSupply::new(root.path(), true, None).unwrap();
+
+        laptop(root.path(), "1", "80");
+        assert!(power_supply.check());
+
+        laptop(root.path(), "0", "80");
+        assert!(!power_supply.check());
+
+        laptop(root.path(), "1", "80");
+        assert!(power_supply.check());
+    }
+
+    #[test]
+    fn test_release_below_battery_percent() {
+        let root = tempfile::tempdir().unwrap();
+        let mut power_supply = PowerSupply::new(root.path(), false, Some(20)).unwrap();
+
+        laptop(root.path(), "0", "50");
+        assert!(power_supply.check());
+
+        laptop(root.path(), "0", "19");
+        assert!(!power_supply.check());
+
+        // Charging
+        laptop(root.path(), "1", "19");
+        assert!(power_supply.check());
+    }
+}
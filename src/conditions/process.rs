@@ -0,0 +1,268 @@
+//! Keeping the system awake while a matching process runs (`KEEP_AWAKE_WHILE_PROCESS`)
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use tracing::info;
+
+use super::Condition;
+
+#[derive(Debug)]
+pub(crate) struct ProcessRunning {
+    /// Patterns matched against the whole process name, or the start of its command line
+    patterns: Vec<(Regex, Regex)>,
+    proc_root: PathBuf,
+    /// Matching processes found by the previous check, PID to name
+    running: BTreeMap<u32, String>,
+}
+
+impl ProcessRunning {
+    pub(crate) fn new(patterns: &[String]) -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            anyhow::bail!("KEEP_AWAKE_WHILE_PROCESS is only supported on Linux");
+        }
+
+        Self::with_root(patterns, PathBuf::from("/proc"))
+    }
+
+    fn with_root(patterns: &[String], proc_root: PathBuf) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| -> Result<_> {
+                let name = Regex::new(&format!("^(?:{pattern})$"))?;
+                // Whole arguments from the program on, so that `rsync` doesn't match `grep rsync` or `rsync.conf`
+                let command_line = Regex::new(&format!(r"^(?:{pattern})(?:\s|$)"))?;
+                Ok((name, command_line))
+            })
+            .collect::<Result<_>>()
+            .context("Invalid KEEP_AWAKE_WHILE_PROCESS pattern")?;
+
+        Ok(Self {
+            patterns,
+            proc_root,
+            running: BTreeMap::new(),
+        })
+    }
+
+    /// Finds all processes matching any of the patterns
+    fn scan(&self) -> BTreeMap<u32, String> {
+        let Ok(entries) = fs::read_dir(&self.proc_root) else {
+            return BTreeMap::new();
+        };
+
+        let own_pid = std::process::id();
+
+        entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+            .filter(|&pid| pid != own_pid)
+            .filter_map(|pid| {
+                let dir = self.proc_root.join(pid.to_string());
+                let name = read_comm(&dir)?;
+                let command_lines = command_lines(&read_cmdline(&dir).unwrap_or_default());
+
+                self.patterns
+                    .iter()
+                    .any(|(name_pattern, command_line_pattern)| {
+                        name_pattern.is_match(&name)
+                            || command_lines
+                                .iter()
+                                .any(|command_line| command_line_pattern.is_match(command_line))
+                    })
+                    .then_some((pid, name))
+            })
+            .collect()
+    }
+}
+
+/// Process name, as shown by `ps`
+fn read_comm(dir: &Path) -> Option<String> {
+    let comm = fs::read_to_string(dir.join("comm")).ok()?;
+    Some(comm.trim_end_matches('\n').to_owned())
+}
+
+/// Arguments of the process, empty for kernel threads
+fn read_cmdline(dir: &Path) -> Option<Vec<String>> {
+    let cmdline = fs::read(dir.join("cmdline")).ok()?;
+    let args = cmdline
+        .split(|&byte| byte == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+    Some(args)
+}
+
+/// Arguments separated by spaces, with the program as it was started and without its directory
+fn command_lines(args: &[String]) -> Vec<String> {
+    let Some((program, rest)) = args.split_first() else {
+        return Vec::new();
+    };
+
+    let mut command_lines = vec![args.join(" ")];
+
+    if let Some((_, base_name)) = program.rsplit_once('/') {
+        let mut args = vec![base_name.to_owned()];
+        args.extend_from_slice(rest);
+        command_lines.push(args.join(" "));
+    }
+
+    command_lines
+}
+
+impl Condition for ProcessRunning {
+    fn name(&self) -> &'static str {
+        "process"
+    }
+
+    fn check(&mut self) -> bool {
+        let running = self.scan();
+
+        for (pid, name) in &running {
+            if !self.running.contains_key(pid) {
+                info!(pid, name, "Matching process started");
+            }
+        }
+
+        for (pid, name) in &self.running {
+            if !running.contains_key(pid) {
+                info!(pid, name, "Matching process stopped");
+            }
+        }
+
+        self.running = running;
+        !self.running.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_process(root: &Path, pid: u32, name: &str, args: &[&str]) {
+        let dir = root.join(pid.to_string());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("comm"), format!("{name}\n")).unwrap();
+        let cmdline: Vec<u8> = args
+            .iter()
+            .flat_map(|arg| [arg.as_bytes(), b"\0"].concat())
+            .collect();
+        fs::write(dir.join("cmdline"), cmdline).unwrap();
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn test_matching() {
+        let root = tempfile::tempdir().unwrap();
+        fake_process(root.path(), 1, "systemd", &["/sbin/init"]);
+        fake_process(root.path(), 2, "kthreadd", &[]);
+        fake_process(
+            root.path(),
+            10,
+            "rsync-helper",
+            &["/usr/libexec/sync-helper"],
+        );
+        fs::create_dir(root.path().join("sys")).unwrap();
+
+        fake_process(
+            root.path(),
+            11,
+            "grep",
+            &["grep", "rsync", "/var/log/syslog"],
+        );
+        fake_process(root.path(), 12, "vim", &["vim", "rsync.conf"]);
+        fake_process(root.path(), 13, "python3", &["python3", "eval.py"]);
+
+        let mut condition = ProcessRunning::with_root(
+            &patterns(&["rsync", "ffmpeg", r"python3 .*train\.py"]),
+            root.path().to_owned(),
+        )
+        .unwrap();
+
+        // Names have to match as a whole, command lines from their start
+        assert!(!condition.check());
+
+        fake_process(
+            root.path(),
+            20,
+            "ffmpeg",
+            &["ffmpeg", "-i", "in.mkv", "out.mp4"],
+        );
+        fake_process(
+            root.path(),
+            30,
+            "python3",
+            &["python3", "models/train.py", "--epochs=3"],
+        );
+        assert!(condition.check());
+        assert_eq!(
+            condition.running,
+            BTreeMap::from([(20, "ffmpeg".to_owned()), (30, "python3".to_owned())])
+        );
+
+        // The directory of the program doesn't matter
+        fake_process(
+            root.path(),
+            40,
+            "rs",
+            &["/usr/bin/rsync", "-a", "src", "dst"],
+        );
+        assert!(condition.check());
+        assert!(condition.running.contains_key(&40));
+        fs::remove_dir_all(root.path().join("40")).unwrap();
+
+        fs::remove_dir_all(root.path().join("20")).unwrap();
+        assert!(condition.check());
+
+        fs::remove_dir_all(root.path().join("30")).unwrap();
+        assert!(!condition.check());
+        assert!(condition.running.is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_real_process() {
+        // A name of its own, other tests run `sleep` as well
+        let dir = tempfile::tempdir().unwrap();
+        let name = format!("nap-{}", std::process::id());
+        let program = dir.path().join(&name);
+        std::os::unix::fs::symlink("/bin/sleep", &program).unwrap();
+
+        let mut condition =
+            ProcessRunning::with_root(&patterns(&[&name]), PathBuf::from("/proc")).unwrap();
+        assert!(!condition.check());
+
+        let mut child = std::process::Command::new(&program)
+            .arg("30")
+            .spawn()
+            .unwrap();
+
+        // Until the child calls exec it is a copy of the test process
+        let comm = PathBuf::from(format!("/proc/{}/comm", child.id()));
+        while fs::read_to_string(&comm).unwrap() != format!("{name}\n") {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        assert!(condition.check());
+        assert!(condition.running.contains_key(&child.id()));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(!condition.check());
+        assert!(condition.running.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let result =
+            ProcessRunning::with_root(&patterns(&["rsync", "(ffmpeg"]), PathBuf::from("/proc"));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,319 @@
+//! Keeping the system awake during working hours (`SCHEDULE`)
+
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, Days, NaiveDateTime, TimeDelta, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{de::Error, Deserialize, Deserializer};
+use tracing::info;
+
+use super::{
+    time::{TimeRange, Zone},
+    Condition,
+};
+
+/// Days of the week and hours of a window, e.g. `Mon-Fri 08:30-17:30`
+#[derive(Debug, Clone, PartialEq)]
+struct Window {
+    /// Indexed by the number of days since Monday
+    days: [bool; 7],
+    hours: TimeRange,
+}
+
+impl Window {
+    fn contains(&self, now: NaiveDateTime) -> bool {
+        let day = now.weekday();
+        let minute = now.hour() * 60 + now.minute();
+        let on = |day: Weekday| self.days[day.num_days_from_monday() as usize];
+
+        if self.hours.start < self.hours.end {
+            on(day) && self.hours.contains(minute)
+        } else {
+            // The window started on the previous day if it is already past midnight
+            (on(day) && minute >= self.hours.start) || (on(day.pred()) && minute < self.hours.end)
+        }
+    }
+}
+
+/// Windows during which the system is kept awake, e.g. `Mon-Fri 08:30-17:30; Sat 10:00-12:00`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Schedule {
+    windows: Vec<Window>,
+}
+
+impl Schedule {
+    pub(crate) fn contains(&self, now: NaiveDateTime) -> bool {
+        self.windows.iter().any(|window| window.contains(now))
+    }
+
+    /// Start of the first window after `now`
+    pub(crate) fn next_start(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        // Every window starts at least once a week
+        (0..=7)
+            .filter_map(|days| now.date().checked_add_days(Days::new(days)))
+            .flat_map(|date| {
+                self.windows
+                    .iter()
+                    .filter(move |window| {
+                        window.days[date.weekday().num_days_from_monday() as usize]
+                    })
+                    .map(move |window| {
+                        date.and_time(Default::default())
+                            + TimeDelta::minutes(window.hours.start.into())
+                    })
+            })
+            .filter(|start| *start > now)
+            .min()
+    }
+}
+
+/// Parses `Mon-Fri`, `Sat`, `Mon,Wed,Fri` or `Fri-Mon`
+fn parse_days(s: &str) -> Result<[bool; 7]> {
+    let parse_day = |day: &str| {
+        day.trim()
+            .parse::<Weekday>()
+            .map_err(|_| anyhow!("invalid day of the week `{day}`"))
+    };
+
+    let mut days = [false; 7];
+
+    for part in s.split(',') {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (parse_day(first)?, parse_day(last)?),
+            None => (parse_day(part)?, parse_day(part)?),
+        };
+
+        let mut day = first;
+        loop {
+            days[day.num_days_from_monday() as usize] = true;
+            if day == last {
+                break;
+            }
+            day = day.succ();
+        }
+    }
+
+    Ok(days)
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    /// Parses windows separated by `;`, each of them made of days (every day if omitted) and hours separated by `,`
+    fn from_str(s: &str) -> Result<Self> {
+        let mut windows = Vec::new();
+
+        for entry in s
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (days, hours) = match entry.split_whitespace().collect::<Vec<_>>()[..] {
+                [hours] => ([true; 7], hours),
+                [days, hours] => (parse_days(days)?, hours),
+                _ => bail!(r#"expected format: "Mon-Fri 08:30-17:30", got `{entry}`"#),
+            };
+
+            for hours in hours.split(',') {
+                windows.push(Window {
+                    days,
+                    hours: hours.parse()?,
+                });
+            }
+        }
+
+        if windows.is_empty() {
+            bail!("schedule is empty");
+        }
+
+        Ok(Self { windows })
+    }
+}
+
+impl<'de> Deserialize<'de> for Schedule {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = Deserialize::deserialize(deserializer)?;
+
+        s.parse()
+            .map_err(|err| D::Error::custom(format!("[SCHEDULE ERROR] {err}")))
+    }
+}
+
+/// Holds during the windows of a schedule, in the given time zone or the local one
+#[derive(Debug)]
+pub(crate) struct InSchedule {
+    schedule: Schedule,
+    zone: Zone,
+    inside: Option<bool>,
+    /// Start of the next window while outside of the schedule
+    next_start: Option<DateTime<Utc>>,
+}
+
+impl InSchedule {
+    pub(crate) fn new(schedule: Schedule, timezone: Option<Tz>) -> Self {
+        Self {
+            schedule,
+            zone: Zone::new(timezone),
+            inside: None,
+            next_start: None,
+        }
+    }
+
+    fn check_at(&mut self, now: DateTime<Utc>) -> bool {
+        let local = self.zone.local(now);
+        let inside = self.schedule.contains(local);
+
+        self.next_start = if inside {
+            None
+        } else {
+            self.schedule
+                .next_start(local)
+                .map(|start| self.zone.utc(start))
+        };
+
+        if self.inside != Some(inside) {
+            if inside {
+                info!("Schedule window started");
+            } else if let Some(start) = self.next_start {
+                info!(next_window = %self.zone.local(start).format("%a %Y-%m-%d %H:%M"), "Outside of the schedule");
+            }
+            self.inside = Some(inside);
+        }
+
+        inside
+    }
+}
+
+impl Condition for InSchedule {
+    fn name(&self) -> &'static str {
+        "schedule"
+    }
+
+    fn check(&mut self) -> bool {
+        self.check_at(Utc::now())
+    }
+
+    fn holds_again_at(&self) -> Option<Instant> {
+        let until = (self.next_start? - Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO);
+        Some(Instant::now() + until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let schedule: Schedule = "Mon-Fri 08:30-17:30; Sat 10:00-12:00".parse().unwrap();
+        assert_eq!(schedule.windows.len(), 2);
+        assert_eq!(
+            schedule.windows[0].days,
+            [true, true, true, true, true, false, false]
+        );
+        assert_eq!(
+            schedule.windows[1].days,
+            [false, false, false, false, false, true, false]
+        );
+
+        let schedule: Schedule = "Fri-mon,Wednesday 9-12,13-17;".parse().unwrap();
+        assert_eq!(schedule.windows.len(), 2);
+        assert_eq!(
+            schedule.windows[1].days,
+            [true, false, true, false, true, true, true]
+        );
+
+        let schedule: Schedule = "22-6".parse().unwrap();
+        assert_eq!(schedule.windows[0].days, [true; 7]);
+
+        for source in [
+            "",
+            " ; ",
+            "Mon-Fri",
+            "Mon-Fri 9",
+            "Mon-Fry 9-17",
+            "Mon - Fri 9-17",
+            "Mon-Fri 9-17 UTC",
+        ] {
+            assert!(
+                source.parse::<Schedule>().is_err(),
+                "{source:?} should not be valid"
+            );
+        }
+    }
+
+    #[test]
+    fn test_contains() {
+        let schedule: Schedule = "Mon-Fri 08:30-17:30; Sat 10:00-12:00; Fri 22-2"
+            .parse()
+            .unwrap();
+
+        // 2026-10-16 is a Friday
+        assert!(!schedule.contains(at("2026-10-16 08:29")));
+        assert!(schedule.contains(at("2026-10-16 08:30")));
+        assert!(!schedule.contains(at("2026-10-16 17:30")));
+        assert!(schedule.contains(at("2026-10-16 23:00")));
+        assert!(schedule.contains(at("2026-10-17 01:59")));
+        assert!(!schedule.contains(at("2026-10-17 02:00")));
+        assert!(schedule.contains(at("2026-10-17 11:00")));
+        assert!(!schedule.contains(at("2026-10-18 11:00")));
+        // Only the night from Friday to Saturday
+        assert!(!schedule.contains(at("2026-10-16 01:00")));
+    }
+
+    #[test]
+    fn test_next_start() {
+        let schedule: Schedule = "Mon-Fri 08:30-17:30; Sat 10:00-12:00".parse().unwrap();
+
+        assert_eq!(
+            schedule.next_start(at("2026-10-16 07:00")),
+            Some(at("2026-10-16 08:30"))
+        );
+        assert_eq!(
+            schedule.next_start(at("2026-10-16 18:00")),
+            Some(at("2026-10-17 10:00"))
+        );
+        assert_eq!(
+            schedule.next_start(at("2026-10-17 12:00")),
+            Some(at("2026-10-19 08:30"))
+        );
+
+        let schedule: Schedule = "Wed 9-17".parse().unwrap();
+        assert_eq!(
+            schedule.next_start(at("2026-10-14 09:00")),
+            Some(at("2026-10-21 09:00"))
+        );
+    }
+
+    #[test]
+    fn test_timezone() {
+        let schedule: Schedule = "Mon-Fri 08:30-17:30".parse().unwrap();
+        let mut in_schedule = InSchedule::new(schedule, Some(chrono_tz::Europe::Warsaw));
+        let utc = |s: &str| at(s).and_utc();
+
+        // Central European Summer Time is 2 hours ahead of UTC
+        assert!(!in_schedule.check_at(utc("2026-10-16 06:00")));
+        assert_eq!(in_schedule.next_start, Some(utc("2026-10-16 06:30")));
+        assert!(in_schedule.check_at(utc("2026-10-16 06:30")));
+        assert_eq!(in_schedule.next_start, None);
+        assert_eq!(in_schedule.holds_again_at(), None);
+
+        // Winter time starts on Sunday, 2026-10-25
+        assert!(!in_schedule.check_at(utc("2026-10-23 15:30")));
+        assert_eq!(in_schedule.next_start, Some(utc("2026-10-26 07:30")));
+        assert!(in_schedule.holds_again_at().is_some());
+    }
+}
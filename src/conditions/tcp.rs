@@ -0,0 +1,133 @@
+//! Keeping the system awake while somebody is connected, e.g. over SSH
+
+use std::{fs, path::PathBuf};
+
+use anyhow::Result;
+use tracing::info;
+
+use super::Condition;
+
+/// `st` column value of established connections, see `include/net/tcp_states.h`
+const TCP_ESTABLISHED: &str = "01";
+
+#[derive(Debug)]
+pub(crate) struct TcpSessions {
+    /// Local ports the sessions are counted on
+    ports: Vec<u16>,
+    proc_root: PathBuf,
+    /// Number of sessions found by the previous check
+    sessions: usize,
+}
+
+impl TcpSessions {
+    pub(crate) fn new(ports: &[u16]) -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            anyhow::bail!("TCP sessions can only be monitored on Linux");
+        }
+
+        Ok(Self::with_root(ports, PathBuf::from("/proc")))
+    }
+
+    fn with_root(ports: &[u16], proc_root: PathBuf) -> Self {
+        Self {
+            ports: ports.to_vec(),
+            proc_root,
+            sessions: 0,
+        }
+    }
+
+    fn count(&self) -> usize {
+        ["net/tcp", "net/tcp6"]
+            .into_iter()
+            .filter_map(|table| fs::read_to_string(self.proc_root.join(table)).ok())
+            .map(|table| count_established(&table, &self.ports))
+            .sum()
+    }
+}
+
+/// Counts established connections on the local `ports` in a `/proc/net/tcp` (or `tcp6`) table
+fn count_established(table: &str, ports: &[u16]) -> usize {
+    table
+        .lines()
+        // The first line is a header
+        .skip(1)
+        .filter(|line| {
+            let mut columns = line.split_whitespace().skip(1);
+            let (Some(local_address), Some(_remote_address), Some(state)) =
+                (columns.next(), columns.next(), columns.next())
+            else {
+                return false;
+            };
+
+            let local_port = local_address
+                .rsplit_once(':')
+                .and_then(|(_, port)| u16::from_str_radix(port, 16).ok());
+
+            state == TCP_ESTABLISHED && local_port.is_some_and(|port| ports.contains(&port))
+        })
+        .count()
+}
+
+impl Condition for TcpSessions {
+    fn name(&self) -> &'static str {
+        "tcp"
+    }
+
+    fn check(&mut self) -> bool {
+        let sessions = self.count();
+
+        if sessions != self.sessions {
+            info!(sessions, ports = ?self.ports, "Number of established TCP sessions changed");
+            self.sessions = sessions;
+        }
+
+        sessions > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TCP: &str = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:0016 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 20121 1 0000000000000000 100 0 0 10 0
+   1: 0F02000A:0016 0202000A:D9A4 01 00000000:00000000 02:0009C4D5 00000000     0        0 51133 4 0000000000000000 20 4 31 10 -1
+   2: 0F02000A:D9A6 0202000A:0016 01 00000000:00000000 02:0009C4D5 00000000  1000        0 51134 4 0000000000000000 20 4 31 10 -1
+   3: 0F02000A:1F90 0202000A:C001 01 00000000:00000000 02:0009C4D5 00000000  1000        0 51135 4 0000000000000000 20 4 31 10 -1
+   4: 0F02000A:0016 0202000A:D9A8 06 00000000:00000000 03:00000DAB 00000000     0        0 0 3 0000000000000000
+";
+
+    const TCP6: &str = "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000000000000000000000000000:0016 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 20123 1 0000000000000000 100 0 0 10 0
+   1: 0000000000000000FFFF00000F02000A:0016 0000000000000000FFFF00000202000A:E1B2 01 00000000:00000000 02:0009C4D5 00000000     0        0 51140 4 0000000000000000 20 4 31 10 -1
+";
+
+    #[test]
+    fn test_count_established() {
+        // Listening sockets, outgoing connections to port 22 and closing connections are not sessions
+        assert_eq!(count_established(TCP, &[22]), 1);
+        assert_eq!(count_established(TCP, &[22, 8080]), 2);
+        assert_eq!(count_established(TCP, &[443]), 0);
+        assert_eq!(count_established(TCP6, &[22]), 1);
+        assert_eq!(count_established("", &[22]), 0);
+    }
+
+    #[test]
+    fn test_sessions() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("net")).unwrap();
+        let mut sessions = TcpSessions::with_root(&[22], root.path().to_owned());
+
+        assert!(!sessions.check());
+
+        fs::write(root.path().join("net/tcp"), TCP).unwrap();
+        fs::write(root.path().join("net/tcp6"), TCP6).unwrap();
+        assert!(sessions.check());
+        assert_eq!(sessions.sessions, 2);
+
+        fs::write(root.path().join("net/tcp"), "header\n").unwrap();
+        fs::write(root.path().join("net/tcp6"), "header\n").unwrap();
+        assert!(!sessions.check());
+        assert_eq!(sessions.sessions, 0);
+    }
+}
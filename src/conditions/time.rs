@@ -0,0 +1,240 @@
+//! Conditions on the local time
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{
+    DateTime, Datelike, Local, NaiveDateTime, TimeDelta, TimeZone, Timelike, Utc, Weekday,
+};
+use chrono_tz::Tz;
+
+use super::Condition;
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// Time zone in which local times are given
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Zone {
+    /// The time zone of the system
+    Local,
+    Utc,
+    Tz(Tz),
+}
+
+impl Zone {
+    /// The given time zone, or the local one
+    pub(crate) fn new(timezone: Option<Tz>) -> Self {
+        timezone.map_or(Zone::Local, Zone::Tz)
+    }
+
+    pub(crate) fn local(self, time: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Zone::Local => time.with_timezone(&Local).naive_local(),
+            Zone::Utc => time.naive_utc(),
+            Zone::Tz(timezone) => time.with_timezone(&timezone).naive_local(),
+        }
+    }
+
+    /// The earliest point in time with the given local time, or an hour later if it was skipped by a daylight saving
+    /// time change
+    pub(crate) fn utc(self, local: NaiveDateTime) -> DateTime<Utc> {
+        fn convert(timezone: impl TimeZone, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+            timezone
+                .from_local_datetime(&local)
+                .earliest()
+                .or_else(|| {
+                    timezone
+                        .from_local_datetime(&(local + TimeDelta::hours(1)))
+                        .earliest()
+                })
+                .map(|time| time.with_timezone(&Utc))
+        }
+
+        match self {
+            Zone::Local => convert(Local, local),
+            Zone::Utc => Some(local.and_utc()),
+            Zone::Tz(timezone) => convert(timezone, local),
+        }
+        .unwrap_or_else(|| local.and_utc())
+    }
+}
+
+/// Part of a day, from `start` (inclusive) to `end` (exclusive), in minutes since midnight
+///
+/// A range ending before its start continues past midnight, e.g. `22-6`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TimeRange {
+    pub(crate) start: u32,
+    pub(crate) end: u32,
+}
+
+impl TimeRange {
+    pub(crate) fn contains(&self, minute: u32) -> bool {
+        if self.start <= self.end {
+            self.start <= minute && minute < self.end
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+/// Parses `9`, `09:30` or `24` into minutes since midnight
+fn parse_time_of_day(s: &str) -> Result<u32> {
+    let (hours, minutes) = s.split_once(':').unwrap_or((s, "0"));
+    let hours: u32 = hours.trim().parse()?;
+    let minutes: u32 = minutes.trim().parse()?;
+
+    if minutes >= 60 || hours * 60 + minutes > MINUTES_PER_DAY {
+        bail!("invalid time of day `{s}`");
+    }
+
+    Ok(hours * 60 + minutes)
+}
+
+impl FromStr for TimeRange {
+    type Err = anyhow::Error;
+
+    /// Parses `9-17` or `08:30-17:30`
+    fn from_str(s: &str) -> Result<Self> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow!(r#"expected format: "09:00-17:00", got `{s}`"#))?;
+
+        let range = TimeRange {
+            start: parse_time_of_day(start)?,
+            end: parse_time_of_day(end)?,
+        };
+
+        if range.start == range.end {
+            bail!("time range `{s}` is empty");
+        }
+
+        Ok(range)
+    }
+}
+
+impl fmt::Display for TimeRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+/// Holds on working days (Monday to Friday) or on weekends
+#[derive(Debug)]
+pub(crate) struct DayOfWeek {
+    weekend: bool,
+}
+
+impl DayOfWeek {
+    pub(crate) fn weekdays() -> Self {
+        Self { weekend: false }
+    }
+
+    pub(crate) fn weekend() -> Self {
+        Self { weekend: true }
+    }
+
+    fn holds_at(&self, now: NaiveDateTime) -> bool {
+        matches!(now.weekday(), Weekday::Sat | Weekday::Sun) == self.weekend
+    }
+}
+
+impl Condition for DayOfWeek {
+    fn name(&self) -> &'static str {
+        if self.weekend {
+            "weekend"
+        } else {
+            "weekday"
+        }
+    }
+
+    fn check(&mut self) -> bool {
+        self.holds_at(Local::now().naive_local())
+    }
+}
+
+/// Holds during a part of every day
+#[derive(Debug)]
+pub(crate) struct Hours {
+    range: TimeRange,
+}
+
+impl Hours {
+    pub(crate) fn new(range: TimeRange) -> Self {
+        Self { range }
+    }
+
+    fn holds_at(&self, now: NaiveDateTime) -> bool {
+        self.range.contains(now.hour() * 60 + now.minute())
+    }
+}
+
+impl Condition for Hours {
+    fn name(&self) -> &'static str {
+        "hours"
+    }
+
+    fn check(&mut self) -> bool {
+        self.holds_at(Local::now().naive_local())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_time_range() {
+        let range: TimeRange = "9-17".parse().unwrap();
+        assert_eq!(
+            range,
+            TimeRange {
+                start: 540,
+                end: 1020
+            }
+        );
+        assert_eq!(range.to_string(), "09:00-17:00");
+        assert!(!range.contains(539));
+        assert!(range.contains(540));
+        assert!(!range.contains(1020));
+
+        let range: TimeRange = "22:30-06:00".parse().unwrap();
+        assert!(range.contains(23 * 60));
+        assert!(range.contains(5 * 60));
+        assert!(!range.contains(12 * 60));
+
+        let range: TimeRange = "0-24".parse().unwrap();
+        assert!(range.contains(0));
+        assert!(range.contains(MINUTES_PER_DAY - 1));
+
+        assert!("9".parse::<TimeRange>().is_err());
+        assert!("9-9".parse::<TimeRange>().is_err());
+        assert!("9-25".parse::<TimeRange>().is_err());
+        assert!("9:60-17".parse::<TimeRange>().is_err());
+        assert!("nine-17".parse::<TimeRange>().is_err());
+    }
+
+    #[test]
+    fn test_conditions() {
+        // 2026-10-16 is a Friday
+        assert!(DayOfWeek::weekdays().holds_at(at("2026-10-16 12:00")));
+        assert!(!DayOfWeek::weekend().holds_at(at("2026-10-16 12:00")));
+        assert!(!DayOfWeek::weekdays().holds_at(at("2026-10-17 12:00")));
+        assert!(DayOfWeek::weekend().holds_at(at("2026-10-18 12:00")));
+
+        let hours = Hours::new("9-17".parse().unwrap());
+        assert!(hours.holds_at(at("2026-10-16 09:00")));
+        assert!(!hours.holds_at(at("2026-10-16 17:00")));
+    }
+}
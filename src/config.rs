@@ -1,10 +1,15 @@
 use crate::{
+    conditions::{calendar::Filters, schedule::Schedule},
     config::ConfigError::InvalidProperty,
-    models::{InitPoint, WorkingArea},
+    idle,
+    models::{InitPoint, Mode, UsbId, WorkingArea},
+    power::{self, InhibitKind, LockRequest},
 };
+use chrono_tz::Tz;
+use regex::Regex;
 use serde::Deserialize;
-use serde_with::{serde_as, DurationSeconds};
-use std::time::Duration;
+use serde_with::{formats::SemicolonSeparator, serde_as, DurationSeconds, StringWithSeparator};
+use std::{collections::HashMap, path::PathBuf, time::Duration};
 use thiserror::Error;
 
 /// Provides default value for stayawake_interval if STAYAWAKE_INTERVAL env var is not set
@@ -12,6 +17,11 @@ fn default_stayawake_interval() -> Duration {
     Duration::from_secs(15)
 }
 
+/// Provides default value for stayawake_mode if STAYAWAKE_MODE env var is not set
+fn default_stayawake_mode() -> Mode {
+    Mode::Both
+}
+
 /// Provides default value for jump_by_pixel_min if JUMP_BY_PIXEL_MIN env var is not set
 fn default_jump_by_pixel_min() -> usize {
     100
@@ -22,6 +32,11 @@ fn default_jump_by_pixel_max() -> usize {
     150
 }
 
+/// Provides default value for movement_deadzone_px if MOVEMENT_DEADZONE_PX env var is not set (any displacement is a movement)
+fn default_movement_deadzone_px() -> u32 {
+    0
+}
+
 /// Provides default value for init_point if INIT_POINT env var is not set
 fn default_init_point() -> InitPoint {
     InitPoint { x: 0, y: 0 }
@@ -35,6 +50,181 @@ fn default_working_area() -> WorkingArea {
     }
 }
 
+/// Provides default value for power_backends if POWER_BACKENDS env var is not set (platform's default order)
+fn default_power_backends() -> Vec<String> {
+    Vec::new()
+}
+
+/// Provides default value for idle_threshold if IDLE_THRESHOLD env var is not set (same as stayawake_interval)
+fn default_idle_threshold() -> Option<Duration> {
+    None
+}
+
+/// Provides default value for sample_every_ms if SAMPLE_EVERY_MS env var is not set (sampled at every check)
+fn default_sample_every_ms() -> Option<u64> {
+    None
+}
+
+/// Provides default value for idle_sources if IDLE_SOURCES env var is not set (platform's default order)
+fn default_idle_sources() -> Vec<String> {
+    Vec::new()
+}
+
+/// Provides default value for evdev_devices if EVDEV_DEVICES env var is not set (every `/dev/input/event*` device)
+fn default_evdev_devices() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+/// Provides default value for power_lock_kinds if POWER_LOCK_KINDS env var is not set
+fn default_power_lock_kinds() -> Vec<InhibitKind> {
+    power::ALL_INHIBIT_KINDS.to_vec()
+}
+
+/// Provides default value for power_lock_who if POWER_LOCK_WHO env var is not set
+fn default_power_lock_who() -> String {
+    power::POWER_DEFAULT_WHO.to_owned()
+}
+
+/// Provides default value for power_lock_reason if POWER_LOCK_REASON env var is not set
+fn default_power_lock_reason() -> String {
+    power::POWER_DEFAULT_MESSAGE.to_owned()
+}
+
+/// Provides default value for keep_awake_while_process if KEEP_AWAKE_WHILE_PROCESS env var is not set
+fn default_keep_awake_while_process() -> Vec<String> {
+    Vec::new()
+}
+
+/// Provides default value for cpu_busy_percent if CPU_BUSY_PERCENT env var is not set (not checked)
+fn default_cpu_busy_percent() -> Option<u8> {
+    None
+}
+
+/// Provides default value for disk_busy_kbps if DISK_BUSY_KBPS env var is not set (not checked)
+fn default_disk_busy_kbps() -> Option<u64> {
+    None
+}
+
+/// Provides default value for net_busy_kbps if NET_BUSY_KBPS env var is not set (not checked)
+fn default_net_busy_kbps() -> Option<u64> {
+    None
+}
+
+/// Provides default value for activity_quiet_period if ACTIVITY_QUIET_PERIOD env var is not set
+fn default_activity_quiet_period() -> Duration {
+    Duration::from_secs(300)
+}
+
+/// Provides default value for keep_awake_while_tcp_sessions if KEEP_AWAKE_WHILE_TCP_SESSIONS env var is not set
+fn default_keep_awake_while_tcp_sessions() -> bool {
+    false
+}
+
+/// Provides default value for tcp_session_ports if TCP_SESSION_PORTS env var is not set (SSH)
+fn default_tcp_session_ports() -> Vec<u16> {
+    vec![22]
+}
+
+/// Provides default value for keep_awake_while_path_changes if KEEP_AWAKE_WHILE_PATH_CHANGES env var is not set (not watched)
+fn default_keep_awake_while_path_changes() -> Option<PathBuf> {
+    None
+}
+
+/// Provides default value for path_changes_quiet_period if PATH_CHANGES_QUIET_PERIOD env var is not set
+fn default_path_changes_quiet_period() -> Duration {
+    Duration::from_secs(300)
+}
+
+/// Provides default value for keep_awake_while_file_exists if KEEP_AWAKE_WHILE_FILE_EXISTS env var is not set (not checked)
+fn default_keep_awake_while_file_exists() -> Option<PathBuf> {
+    None
+}
+
+/// Provides default value for keep_awake_while_external_monitor if KEEP_AWAKE_WHILE_EXTERNAL_MONITOR env var is not set
+fn default_keep_awake_while_external_monitor() -> bool {
+    false
+}
+
+/// Provides default value for keep_awake_while_usb_device if KEEP_AWAKE_WHILE_USB_DEVICE env var is not set
+fn default_keep_awake_while_usb_device() -> Vec<UsbId> {
+    Vec::new()
+}
+
+/// Provides default value for sysfs_root if SYSFS_ROOT env var is not set
+fn default_sysfs_root() -> PathBuf {
+    PathBuf::from("/sys")
+}
+
+/// Provides default value for only_on_ac if ONLY_ON_AC env var is not set
+fn default_only_on_ac() -> bool {
+    false
+}
+
+/// Provides default value for release_below_battery_percent if RELEASE_BELOW_BATTERY_PERCENT env var is not set (not checked)
+fn default_release_below_battery_percent() -> Option<u8> {
+    None
+}
+
+/// Provides default value for policy if POLICY env var is not set
+fn default_policy() -> Option<String> {
+    None
+}
+
+/// Provides default value for policy_file if POLICY_FILE env var is not set
+fn default_policy_file() -> Option<PathBuf> {
+    None
+}
+
+/// Provides default value for plugins if PLUGINS env var is not set
+fn default_plugins() -> Vec<String> {
+    Vec::new()
+}
+
+/// Provides default value for plugin_interval if PLUGIN_INTERVAL env var is not set
+fn default_plugin_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+/// Provides default value for plugin_timeout if PLUGIN_TIMEOUT env var is not set
+fn default_plugin_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// Provides default value for schedule if SCHEDULE env var is not set (always active)
+fn default_schedule() -> Option<Schedule> {
+    None
+}
+
+/// Provides default value for schedule_timezone if SCHEDULE_TIMEZONE env var is not set (local time zone)
+fn default_schedule_timezone() -> Option<Tz> {
+    None
+}
+
+/// Provides default value for calendar_ics if CALENDAR_ICS env var is not set (not checked)
+fn default_calendar_ics() -> Option<PathBuf> {
+    None
+}
+
+/// Provides default value for calendar_minutes_before if CALENDAR_MINUTES_BEFORE env var is not set
+fn default_calendar_minutes_before() -> u64 {
+    5
+}
+
+/// Provides default value for calendar_summary if CALENDAR_SUMMARY env var is not set (any summary)
+fn default_calendar_summary() -> Option<String> {
+    None
+}
+
+/// Provides default value for calendar_categories if CALENDAR_CATEGORIES env var is not set (any category)
+fn default_calendar_categories() -> Vec<String> {
+    Vec::new()
+}
+
+/// Provides default value for power_lock_required if POWER_LOCK_REQUIRED env var is not set
+fn default_power_lock_required() -> bool {
+    false
+}
+
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("Configuration property `{property}` is invalid: {message}")]
@@ -46,22 +236,209 @@ pub enum ConfigError {
 
 /// Configuration struct (.env file)
 #[serde_as]
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub(crate) struct Config {
     #[serde_as(as = "DurationSeconds<u64>")]
     #[serde(default = "default_stayawake_interval")]
     pub(crate) stayawake_interval: Duration,
+    #[serde(default = "default_stayawake_mode")]
+    pub(crate) stayawake_mode: Mode,
     #[serde(default = "default_jump_by_pixel_min")]
     pub(crate) jump_by_pixel_min: usize,
     #[serde(default = "default_jump_by_pixel_max")]
     pub(crate) jump_by_pixel_max: usize,
+    #[serde(default = "default_movement_deadzone_px")]
+    pub(crate) movement_deadzone_px: u32,
     #[serde(default = "default_init_point")]
     pub(crate) init_point: InitPoint,
     #[serde(default = "default_working_area")]
     pub(crate) working_area: WorkingArea,
+    #[serde(default = "default_power_backends")]
+    pub(crate) power_backends: Vec<String>,
+    #[serde_as(as = "Option<DurationSeconds<u64>>")]
+    #[serde(default = "default_idle_threshold")]
+    pub(crate) idle_threshold: Option<Duration>,
+    #[serde(default = "default_sample_every_ms")]
+    pub(crate) sample_every_ms: Option<u64>,
+    #[serde(default = "default_idle_sources")]
+    pub(crate) idle_sources: Vec<String>,
+    #[serde(default = "default_evdev_devices")]
+    pub(crate) evdev_devices: Vec<PathBuf>,
+    #[serde(default = "default_power_lock_kinds")]
+    pub(crate) power_lock_kinds: Vec<InhibitKind>,
+    #[serde(default = "default_power_lock_who")]
+    pub(crate) power_lock_who: String,
+    #[serde(default = "default_power_lock_reason")]
+    pub(crate) power_lock_reason: String,
+    #[serde(default = "default_power_lock_required")]
+    pub(crate) power_lock_required: bool,
+    // Regular expressions often contain commas, e.g. `a{1,3}`
+    #[serde_as(as = "StringWithSeparator::<SemicolonSeparator, String>")]
+    #[serde(default = "default_keep_awake_while_process")]
+    pub(crate) keep_awake_while_process: Vec<String>,
+    #[serde(default = "default_cpu_busy_percent")]
+    pub(crate) cpu_busy_percent: Option<u8>,
+    #[serde(default = "default_disk_busy_kbps")]
+    pub(crate) disk_busy_kbps: Option<u64>,
+    #[serde(default = "default_net_busy_kbps")]
+    pub(crate) net_busy_kbps: Option<u64>,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(default = "default_activity_quiet_period")]
+    pub(crate) activity_quiet_period: Duration,
+    #[serde(default = "default_keep_awake_while_tcp_sessions")]
+    pub(crate) keep_awake_while_tcp_sessions: bool,
+    #[serde(default = "default_tcp_session_ports")]
+    pub(crate) tcp_session_ports: Vec<u16>,
+    #[serde(default = "default_keep_awake_while_path_changes")]
+    pub(crate) keep_awake_while_path_changes: Option<PathBuf>,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(default = "default_path_changes_quiet_period")]
+    pub(crate) path_changes_quiet_period: Duration,
+    #[serde(default = "default_keep_awake_while_file_exists")]
+    pub(crate) keep_awake_while_file_exists: Option<PathBuf>,
+    #[serde(default = "default_keep_awake_while_external_monitor")]
+    pub(crate) keep_awake_while_external_monitor: bool,
+    #[serde(default = "default_keep_awake_while_usb_device")]
+    pub(crate) keep_awake_while_usb_device: Vec<UsbId>,
+    #[serde(default = "default_sysfs_root")]
+    pub(crate) sysfs_root: PathBuf,
+    #[serde(default = "default_only_on_ac")]
+    pub(crate) only_on_ac: bool,
+    #[serde(default = "default_release_below_battery_percent")]
+    pub(crate) release_below_battery_percent: Option<u8>,
+    #[serde(default = "default_policy")]
+    pub(crate) policy: Option<String>,
+    #[serde(default = "default_policy_file")]
+    pub(crate) policy_file: Option<PathBuf>,
+    #[serde(default = "default_plugins")]
+    pub(crate) plugins: Vec<String>,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(default = "default_plugin_interval")]
+    pub(crate) plugin_interval: Duration,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(default = "default_plugin_timeout")]
+    pub(crate) plugin_timeout: Duration,
+    #[serde(default = "default_schedule")]
+    pub(crate) schedule: Option<Schedule>,
+    #[serde(default = "default_schedule_timezone")]
+    pub(crate) schedule_timezone: Option<Tz>,
+    #[serde(default = "default_calendar_ics")]
+    pub(crate) calendar_ics: Option<PathBuf>,
+    #[serde(default = "default_calendar_minutes_before")]
+    pub(crate) calendar_minutes_before: u64,
+    #[serde(default = "default_calendar_summary")]
+    pub(crate) calendar_summary: Option<String>,
+    #[serde(default = "default_calendar_categories")]
+    pub(crate) calendar_categories: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            stayawake_interval: default_stayawake_interval(),
+            stayawake_mode: default_stayawake_mode(),
+            jump_by_pixel_min: default_jump_by_pixel_min(),
+            jump_by_pixel_max: default_jump_by_pixel_max(),
+            movement_deadzone_px: default_movement_deadzone_px(),
+            init_point: default_init_point(),
+            working_area: default_working_area(),
+            power_backends: default_power_backends(),
+            idle_threshold: default_idle_threshold(),
+            sample_every_ms: default_sample_every_ms(),
+            idle_sources: default_idle_sources(),
+            evdev_devices: default_evdev_devices(),
+            power_lock_kinds: default_power_lock_kinds(),
+            power_lock_who: default_power_lock_who(),
+            power_lock_reason: default_power_lock_reason(),
+            power_lock_required: default_power_lock_required(),
+            keep_awake_while_process: default_keep_awake_while_process(),
+            cpu_busy_percent: default_cpu_busy_percent(),
+            disk_busy_kbps: default_disk_busy_kbps(),
+            net_busy_kbps: default_net_busy_kbps(),
+            activity_quiet_period: default_activity_quiet_period(),
+            keep_awake_while_tcp_sessions: default_keep_awake_while_tcp_sessions(),
+            tcp_session_ports: default_tcp_session_ports(),
+            keep_awake_while_path_changes: default_keep_awake_while_path_changes(),
+            path_changes_quiet_period: default_path_changes_quiet_period(),
+            keep_awake_while_file_exists: default_keep_awake_while_file_exists(),
+            keep_awake_while_external_monitor: default_keep_awake_while_external_monitor(),
+            keep_awake_while_usb_device: default_keep_awake_while_usb_device(),
+            sysfs_root: default_sysfs_root(),
+            only_on_ac: default_only_on_ac(),
+            release_below_battery_percent: default_release_below_battery_percent(),
+            policy: default_policy(),
+            policy_file: default_policy_file(),
+            plugins: default_plugins(),
+            plugin_interval: default_plugin_interval(),
+            plugin_timeout: default_plugin_timeout(),
+            schedule: default_schedule(),
+            schedule_timezone: default_schedule_timezone(),
+            calendar_ics: default_calendar_ics(),
+            calendar_minutes_before: default_calendar_minutes_before(),
+            calendar_summary: default_calendar_summary(),
+            calendar_categories: default_calendar_categories(),
+        }
+    }
+}
+
+/// Variables of the .env file, overridden by the ones of the process environment
+fn layer_env(
+    dotenv_vars: Vec<(String, String)>,
+    process_env: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    let mut vars: HashMap<String, String> = dotenv_vars.into_iter().collect();
+    vars.extend(process_env.clone());
+    vars
 }
 
 impl Config {
+    /// Reads the configuration again, with the same precedence as at startup: the variables of `process_env` (the
+    /// environment before the .env file was loaded) override the ones of the .env file
+    pub(crate) fn reload(process_env: &HashMap<String, String>) -> anyhow::Result<Self> {
+        // Its replacement only loads variables into the environment, without overriding them
+        #[allow(deprecated)]
+        let dotenv_vars = match dotenv::dotenv_iter() {
+            Ok(dotenv_vars) => dotenv_vars.collect::<Result<Vec<_>, _>>()?,
+            Err(_) => Vec::new(),
+        };
+
+        let config = envy::from_iter::<_, Config>(layer_env(dotenv_vars, process_env))?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Power management lock request as configured
+    pub(crate) fn power_lock_request(&self) -> LockRequest {
+        LockRequest {
+            kinds: self.power_lock_kinds.iter().copied().collect(),
+            who: self.power_lock_who.clone(),
+            reason: self.power_lock_reason.clone(),
+        }
+    }
+
+    /// How long the user has to be idle before the mouse is moved
+    pub(crate) fn idle_threshold(&self) -> Duration {
+        self.idle_threshold.unwrap_or(self.stayawake_interval)
+    }
+
+    /// How often the pointer position is sampled between checks, if at all
+    pub(crate) fn sample_every(&self) -> Option<Duration> {
+        self.sample_every_ms.map(Duration::from_millis)
+    }
+
+    /// Calendar events keeping the system awake
+    pub(crate) fn calendar_filters(&self) -> Filters {
+        Filters {
+            // Validated already
+            summary: self
+                .calendar_summary
+                .as_deref()
+                .and_then(|summary| Regex::new(summary).ok()),
+            categories: self.calendar_categories.clone(),
+        }
+    }
+
     /// Validates if the config is correct
     pub(crate) fn validate(&self) -> Result<(), ConfigError> {
         if self.jump_by_pixel_min == 0 {
@@ -93,6 +470,14 @@ impl Config {
             });
         }
 
+        // the mouse has to be moved farther than the deadzone for the movement to be noticed
+        if self.movement_deadzone_px as usize >= self.jump_by_pixel_min {
+            return Err(InvalidProperty {
+                property: "movement_deadzone_px",
+                message: "movement_deadzone_px has to be smaller than jump_by_pixel_min",
+            });
+        }
+
         // working area size have to be bigger than jump by pixel max
         if self.working_area.width <= self.jump_by_pixel_max
             || self.working_area.height <= self.jump_by_pixel_max
@@ -103,6 +488,145 @@ impl Config {
             });
         }
 
+        if self
+            .power_backends
+            .iter()
+            .any(|name| !power::backend_names().any(|backend| backend == name))
+        {
+            return Err(InvalidProperty {
+                property: "power_backends",
+                message: "power_backends contains a backend that is not available on this platform",
+            });
+        }
+
+        // The idle time is only checked that often
+        if self.idle_threshold() < self.stayawake_interval {
+            return Err(InvalidProperty {
+                property: "idle_threshold",
+                message: "idle_threshold cannot be shorter than stayawake_interval",
+            });
+        }
+
+        if self.sample_every_ms == Some(0) {
+            return Err(InvalidProperty {
+                property: "sample_every_ms",
+                message: "sample_every_ms cannot be equal to zero",
+            });
+        }
+
+        if self
+            .sample_every()
+            .is_some_and(|period| period > self.stayawake_interval)
+        {
+            return Err(InvalidProperty {
+                property: "sample_every_ms",
+                message: "sample_every_ms cannot be longer than stayawake_interval",
+            });
+        }
+
+        if self
+            .idle_sources
+            .iter()
+            .any(|name| !idle::source_names().any(|source| source == name))
+        {
+            return Err(InvalidProperty {
+                property: "idle_sources",
+                message: "idle_sources contains a source that is not available on this platform",
+            });
+        }
+
+        if self.power_lock_kinds.is_empty() {
+            return Err(InvalidProperty {
+                property: "power_lock_kinds",
+                message: "power_lock_kinds cannot be empty",
+            });
+        }
+
+        if self.power_lock_who.trim().is_empty() {
+            return Err(InvalidProperty {
+                property: "power_lock_who",
+                message: "power_lock_who cannot be empty",
+            });
+        }
+
+        if self.power_lock_reason.trim().is_empty() {
+            return Err(InvalidProperty {
+                property: "power_lock_reason",
+                message: "power_lock_reason cannot be empty",
+            });
+        }
+
+        if self
+            .keep_awake_while_process
+            .iter()
+            .any(|pattern| pattern.is_empty() || Regex::new(pattern).is_err())
+        {
+            return Err(InvalidProperty {
+                property: "keep_awake_while_process",
+                message: "keep_awake_while_process must only contain valid regular expressions",
+            });
+        }
+
+        if matches!(self.cpu_busy_percent, Some(percent) if percent == 0 || percent > 100) {
+            return Err(InvalidProperty {
+                property: "cpu_busy_percent",
+                message: "cpu_busy_percent has to be between 1 and 100",
+            });
+        }
+
+        if matches!(self.release_below_battery_percent, Some(percent) if percent > 100) {
+            return Err(InvalidProperty {
+                property: "release_below_battery_percent",
+                message: "release_below_battery_percent cannot be bigger than 100",
+            });
+        }
+
+        if self.policy.is_some() && self.policy_file.is_some() {
+            return Err(InvalidProperty {
+                property: "policy",
+                message: "policy and policy_file cannot be used together",
+            });
+        }
+
+        if self
+            .calendar_summary
+            .as_deref()
+            .is_some_and(|summary| Regex::new(summary).is_err())
+        {
+            return Err(InvalidProperty {
+                property: "calendar_summary",
+                message: "calendar_summary must be a valid regular expression",
+            });
+        }
+
+        if self.calendar_minutes_before > 24 * 60 {
+            return Err(InvalidProperty {
+                property: "calendar_minutes_before",
+                message: "calendar_minutes_before cannot be bigger than a day (1440)",
+            });
+        }
+
+        if self.plugins.iter().any(|command| command.trim().is_empty()) {
+            return Err(InvalidProperty {
+                property: "plugins",
+                message: "plugins cannot contain empty commands",
+            });
+        }
+
+        if self.plugin_timeout.is_zero() {
+            return Err(InvalidProperty {
+                property: "plugin_timeout",
+                message: "plugin_timeout cannot be equal to zero",
+            });
+        }
+
+        if self.keep_awake_while_tcp_sessions && self.tcp_session_ports.is_empty() {
+            return Err(InvalidProperty {
+                property: "tcp_session_ports",
+                message: "tcp_session_ports cannot be empty",
+            });
+        }
+
         Ok(())
     }
 }
@@ -119,6 +643,7 @@ mod tests {
             jump_by_pixel_max: default_jump_by_pixel_max(),
             init_point: default_init_point(),
             working_area: default_working_area(),
+            ..Default::default()
         };
 
         assert!(config.validate().is_ok());
@@ -134,6 +659,7 @@ mod tests {
                 width: 50,
                 height: 500,
             },
+            ..Default::default()
         };
 
         let result = config.validate();
@@ -158,6 +684,7 @@ mod tests {
                 width: 500,
                 height: 50,
             },
+            ..Default::default()
         };
 
         let result = config.validate();
@@ -182,6 +709,7 @@ mod tests {
                 width: 150,
                 height: 150,
             },
+            ..Default::default()
         };
 
         let result = config.validate();
@@ -206,6 +734,7 @@ mod tests {
                 width: 100,
                 height: 100,
             },
+            ..Default::default()
         };
 
         assert!(config.validate().is_ok());
@@ -221,6 +750,7 @@ mod tests {
                 width: 100,
                 height: 100,
             },
+            ..Default::default()
         };
 
         assert!(config.validate().is_ok());
@@ -236,6 +766,7 @@ mod tests {
                 width: 150,
                 height: 150,
             },
+            ..Default::default()
         };
 
         let result = config.validate();
@@ -260,6 +791,7 @@ mod tests {
                 width: 150,
                 height: 150,
             },
+            ..Default::default()
         };
 
         let result = config.validate();
@@ -281,6 +813,7 @@ mod tests {
                 width: 150,
                 height: 150,
             },
+            ..Default::default()
         };
 
         let result = config.validate();
@@ -302,6 +835,7 @@ mod tests {
                 width: 0,
                 height: 0,
             },
+            ..Default::default()
         };
 
         let result = config.validate();
@@ -326,6 +860,7 @@ mod tests {
                 width: 0,
                 height: 150,
             },
+            ..Default::default()
         };
 
         let result = config.validate();
@@ -339,4 +874,649 @@ mod tests {
             "working_area height or/and width cannot be equal to zero"
         );
     }
+
+    #[test]
+    fn test_power_backends() {
+        let config: Config =
+            envy::from_iter([("POWER_BACKENDS".to_owned(), "unknown,other".to_owned())]).unwrap();
+
+        assert_eq!(config.power_backends, ["unknown", "other"]);
+
+        let result = config.validate();
+        assert!(result.is_err());
+
+        let result_err = result.unwrap_err();
+        let InvalidProperty { property, message } = result_err;
+        assert_eq!(property, "power_backends");
+        assert_eq!(
+            message,
+            "power_backends contains a backend that is not available on this platform"
+        );
+
+        // ----------------
+
+        let config = Config {
+            power_backends: power::backend_names().map(str::to_owned).collect(),
+            ..Default::default()
+        };
+
+        assert!(config.validate().is_ok());
+
+        // ----------------
+
+        let config: Config =
+            envy::from_iter([("POWER_BACKENDS".to_owned(), String::new())]).unwrap();
+        assert!(config.power_backends.is_empty());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_movement_deadzone_px() {
+        let config = Config::default();
+        assert_eq!(config.movement_deadzone_px, 0);
+
+        let config: Config =
+            envy::from_iter([("MOVEMENT_DEADZONE_PX".to_owned(), "3".to_owned())]).unwrap();
+        assert_eq!(config.movement_deadzone_px, 3);
+        assert!(config.validate().is_ok());
+
+        // ----------------
+
+        let config = Config {
+            movement_deadzone_px: 100,
+            jump_by_pixel_min: 100,
+            ..Default::default()
+        };
+
+        let result = config.validate();
+        assert!(result.is_err());
+
+        let result_err = result.unwrap_err();
+        let InvalidProperty { property, message } = result_err;
+        assert_eq!(property, "movement_deadzone_px");
+        assert_eq!(
+            message,
+            "movement_deadzone_px has to be smaller than jump_by_pixel_min"
+        );
+    }
+
+    #[test]
+    fn test_idle_threshold() {
+        let config = Config::default();
+        assert_eq!(config.idle_threshold, None);
+        assert_eq!(config.idle_threshold(), config.stayawake_interval);
+
+        let config: Config = envy::from_iter([
+            ("STAYAWAKE_INTERVAL".to_owned(), "5".to_owned()),
+            ("IDLE_THRESHOLD".to_owned(), "240".to_owned()),
+        ])
+        .unwrap();
+        assert_eq!(config.idle_threshold(), Duration::from_secs(240));
+        assert!(config.validate().is_ok());
+
+        // ----------------
+
+        let config: Config = envy::from_iter([
+            ("STAYAWAKE_INTERVAL".to_owned(), "60".to_owned()),
+            ("IDLE_THRESHOLD".to_owned(), "30".to_owned()),
+        ])
+        .unwrap();
+
+        let result = config.validate();
+        assert!(result.is_err());
+
+        let result_err = result.unwrap_err();
+        let InvalidProperty { property, message } = result_err;
+        assert_eq!(property, "idle_threshold");
+        assert_eq!(
+            message,
+            "idle_threshold cannot be shorter than stayawake_interval"
+        );
+    }
+
+    #[test]
+    fn test_sample_every_ms() {
+        let config = Config::default();
+        assert_eq!(config.sample_every(), None);
+
+        let config: Config =
+            envy::from_iter([("SAMPLE_EVERY_MS".to_owned(), "500".to_owned())]).unwrap();
+        assert_eq!(config.sample_every(), Some(Duration::from_millis(500)));
+        assert!(config.validate().is_ok());
+
+        // ----------------
+
+        for (sample_every_ms, message) in [
+            ("0", "sample_every_ms cannot be equal to zero"),
+            (
+                "15001",
+                "sample_every_ms cannot be longer than stayawake_interval",
+            ),
+        ] {
+            let config: Config =
+                envy::from_iter([("SAMPLE_EVERY_MS".to_owned(), sample_every_ms.to_owned())])
+                    .unwrap();
+
+            let result = config.validate();
+            assert!(result.is_err());
+
+            let result_err = result.unwrap_err();
+            let InvalidProperty {
+                property,
+                message: actual,
+            } = result_err;
+            assert_eq!(property, "sample_every_ms");
+            assert_eq!(actual, message);
+        }
+    }
+
+    #[test]
+    fn test_idle_sources() {
+        let config: Config =
+            envy::from_iter([("IDLE_SOURCES".to_owned(), "logind,unknown".to_owned())]).unwrap();
+
+        assert_eq!(config.idle_sources, ["logind", "unknown"]);
+
+        let result = config.validate();
+        assert!(result.is_err());
+
+        let result_err = result.unwrap_err();
+        let InvalidProperty { property, message } = result_err;
+        assert_eq!(property, "idle_sources");
+        assert_eq!(
+            message,
+            "idle_sources contains a source that is not available on this platform"
+        );
+
+        // ----------------
+
+        let config = Config {
+            idle_sources: idle::source_names().map(str::to_owned).collect(),
+            ..Default::default()
+        };
+
+        assert!(config.validate().is_ok());
+
+        // ----------------
+
+        let config: Config = envy::from_iter([(
+            "EVDEV_DEVICES".to_owned(),
+            "/dev/input/event3,/dev/input/by-id/usb-keyboard-event-kbd".to_owned(),
+        )])
+        .unwrap();
+
+        assert_eq!(
+            config.evdev_devices,
+            [
+                PathBuf::from("/dev/input/event3"),
+                PathBuf::from("/dev/input/by-id/usb-keyboard-event-kbd"),
+            ]
+        );
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_power_lock_kinds() {
+        let config: Config = envy::from_iter([(
+            "POWER_LOCK_KINDS".to_owned(),
+            "system-sleep,idle".to_owned(),
+        )])
+        .unwrap();
+
+        assert_eq!(
+            config.power_lock_kinds,
+            [InhibitKind::SystemSleep, InhibitKind::Idle]
+        );
+        assert!(config.validate().is_ok());
+
+        // ----------------
+
+        let config: Result<Config, _> = envy::from_iter([(
+            "POWER_LOCK_KINDS".to_owned(),
+            "system-sleep,screen".to_owned(),
+        )]);
+
+        assert!(config.is_err());
+
+        // ----------------
+
+        let config: Config =
+            envy::from_iter([("POWER_LOCK_KINDS".to_owned(), String::new())]).unwrap();
+
+        let result = config.validate();
+        assert!(result.is_err());
+
+        let result_err = result.unwrap_err();
+        let InvalidProperty { property, message } = result_err;
+        assert_eq!(property, "power_lock_kinds");
+        assert_eq!(message, "power_lock_kinds cannot be empty");
+    }
+
+    #[test]
+    fn test_power_lock_reason() {
+        let config: Config = envy::from_iter([
+            ("POWER_LOCK_WHO".to_owned(), "Backup".to_owned()),
+            (
+                "POWER_LOCK_REASON".to_owned(),
+                "nightly data sync".to_owned(),
+            ),
+        ])
+        .unwrap();
+
+        assert!(config.validate().is_ok());
+
+        let request = config.power_lock_request();
+        assert_eq!(request.who, "Backup");
+        assert_eq!(request.reason, "nightly data sync");
+        assert_eq!(request.kinds.len(), power::ALL_INHIBIT_KINDS.len());
+
+        // ----------------
+
+        let config = Config {
+            power_lock_reason: " ".to_owned(),
+            ..Default::default()
+        };
+
+        let result = config.validate();
+        assert!(result.is_err());
+
+        let result_err = result.unwrap_err();
+        let InvalidProperty { property, message } = result_err;
+        assert_eq!(property, "power_lock_reason");
+        assert_eq!(message, "power_lock_reason cannot be empty");
+    }
+
+    #[test]
+    fn test_reload_precedence() {
+        let dotenv_vars = vec![
+            ("POWER_LOCK_WHO".to_owned(), "Backup".to_owned()),
+            ("POWER_LOCK_REASON".to_owned(), "from .env".to_owned()),
+        ];
+        let process_env = HashMap::from([(
+            "POWER_LOCK_REASON".to_owned(),
+            "from the environment".to_owned(),
+        )]);
+
+        let config: Config = envy::from_iter(layer_env(dotenv_vars, &process_env)).unwrap();
+        assert_eq!(config.power_lock_who, "Backup");
+        assert_eq!(config.power_lock_reason, "from the environment");
+    }
+
+    #[test]
+    fn test_activity() {
+        let config = Config::default();
+        assert_eq!(config.cpu_busy_percent, None);
+        assert_eq!(config.disk_busy_kbps, None);
+        assert_eq!(config.net_busy_kbps, None);
+        assert_eq!(config.activity_quiet_period, Duration::from_secs(300));
+
+        let config: Config = envy::from_iter([
+            ("CPU_BUSY_PERCENT".to_owned(), "20".to_owned()),
+            ("NET_BUSY_KBPS".to_owned(), "500".to_owned()),
+            ("ACTIVITY_QUIET_PERIOD".to_owned(), "600".to_owned()),
+        ])
+        .unwrap();
+
+        assert!(config.validate().is_ok());
+        assert_eq!(config.cpu_busy_percent, Some(20));
+        assert_eq!(config.disk_busy_kbps, None);
+        assert_eq!(config.net_busy_kbps, Some(500));
+        assert_eq!(config.activity_quiet_period, Duration::from_secs(600));
+
+        // ----------------
+
+        let config = Config {
+            cpu_busy_percent: Some(101),
+            ..Default::default()
+        };
+
+        let result = config.validate();
+        assert!(result.is_err());
+
+        let result_err = result.unwrap_err();
+        let InvalidProperty { property, message } = result_err;
+        assert_eq!(property, "cpu_busy_percent");
+        assert_eq!(message, "cpu_busy_percent has to be between 1 and 100");
+    }
+
+    #[test]
+    fn test_tcp_sessions() {
+        let config = Config::default();
+        assert!(!config.keep_awake_while_tcp_sessions);
+        assert_eq!(config.tcp_session_ports, [22]);
+
+        let config: Config = envy::from_iter([
+            (
+                "KEEP_AWAKE_WHILE_TCP_SESSIONS".to_owned(),
+                "true".to_owned(),
+            ),
+            ("TCP_SESSION_PORTS".to_owned(), "22,2222,3389".to_owned()),
+        ])
+        .unwrap();
+
+        assert!(config.validate().is_ok());
+        assert!(config.keep_awake_while_tcp_sessions);
+        assert_eq!(config.tcp_session_ports, [22, 2222, 3389]);
+
+        // ----------------
+
+        let config = Config {
+            keep_awake_while_tcp_sessions: true,
+            tcp_session_ports: Vec::new(),
+            ..Default::default()
+        };
+
+        let result = config.validate();
+        assert!(result.is_err());
+
+        let result_err = result.unwrap_err();
+        let InvalidProperty { property, message } = result_err;
+        assert_eq!(property, "tcp_session_ports");
+        assert_eq!(message, "tcp_session_ports cannot be empty");
+    }
+
+    #[test]
+    fn test_paths() {
+        let config = Config::default();
+        assert_eq!(config.keep_awake_while_path_changes, None);
+        assert_eq!(config.path_changes_quiet_period, Duration::from_secs(300));
+        assert_eq!(config.keep_awake_while_file_exists, None);
+
+        let config: Config = envy::from_iter([
+            (
+                "KEEP_AWAKE_WHILE_PATH_CHANGES".to_owned(),
+                "/home/user/Downloads".to_owned(),
+            ),
+            ("PATH_CHANGES_QUIET_PERIOD".to_owned(), "120".to_owned()),
+            (
+                "KEEP_AWAKE_WHILE_FILE_EXISTS".to_owned(),
+                "/tmp/stayawake.lock".to_owned(),
+            ),
+        ])
+        .unwrap();
+
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.keep_awake_while_path_changes,
+            Some(PathBuf::from("/home/user/Downloads"))
+        );
+        assert_eq!(config.path_changes_quiet_period, Duration::from_secs(120));
+        assert_eq!(
+            config.keep_awake_while_file_exists,
+            Some(PathBuf::from("/tmp/stayawake.lock"))
+        );
+    }
+
+    #[test]
+    fn test_hardware() {
+        let config = Config::default();
+        assert!(!config.keep_awake_while_external_monitor);
+        assert!(config.keep_awake_while_usb_device.is_empty());
+        assert_eq!(config.sysfs_root, PathBuf::from("/sys"));
+
+        let config: Config = envy::from_iter([
+            (
+                "KEEP_AWAKE_WHILE_EXTERNAL_MONITOR".to_owned(),
+                "true".to_owned(),
+            ),
+            (
+                "KEEP_AWAKE_WHILE_USB_DEVICE".to_owned(),
+                "17ef:a396,046d:c52b".to_owned(),
+            ),
+            ("SYSFS_ROOT".to_owned(), "/tmp/sys".to_owned()),
+        ])
+        .unwrap();
+
+        assert!(config.validate().is_ok());
+        assert!(config.keep_awake_while_external_monitor);
+        assert_eq!(config.keep_awake_while_usb_device.len(), 2);
+        assert_eq!(config.sysfs_root, PathBuf::from("/tmp/sys"));
+    }
+
+    #[test]
+    fn test_power_supply() {
+        let config = Config::default();
+        assert!(!config.only_on_ac);
+        assert_eq!(config.release_below_battery_percent, None);
+
+        let config: Config = envy::from_iter([
+            ("ONLY_ON_AC".to_owned(), "true".to_owned()),
+            ("RELEASE_BELOW_BATTERY_PERCENT".to_owned(), "20".to_owned()),
+        ])
+        .unwrap();
+
+        assert!(config.validate().is_ok());
+        assert!(config.only_on_ac);
+        assert_eq!(config.release_below_battery_percent, Some(20));
+
+        // ----------------
+
+        let config = Config {
+            release_below_battery_percent: Some(120),
+            ..Default::default()
+        };
+
+        let result = config.validate();
+        assert!(result.is_err());
+
+        let result_err = result.unwrap_err();
+        let InvalidProperty { property, message } = result_err;
+        assert_eq!(property, "release_below_battery_percent");
+        assert_eq!(
+            message,
+            "release_below_battery_percent cannot be bigger than 100"
+        );
+    }
+
+    #[test]
+    fn test_policy() {
+        let config: Config = envy::from_iter([(
+            "POLICY".to_owned(),
+            "(weekday AND hours(9-17) AND on-ac) OR process(rsync)".to_owned(),
+        )])
+        .unwrap();
+
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.policy.as_deref(),
+            Some("(weekday AND hours(9-17) AND on-ac) OR process(rsync)")
+        );
+
+        // ----------------
+
+        let config = Config {
+            policy: Some("weekday".to_owned()),
+            policy_file: Some(PathBuf::from("policy.toml")),
+            ..Default::default()
+        };
+
+        let result = config.validate();
+        assert!(result.is_err());
+
+        let result_err = result.unwrap_err();
+        let InvalidProperty { property, message } = result_err;
+        assert_eq!(property, "policy");
+        assert_eq!(message, "policy and policy_file cannot be used together");
+    }
+
+    #[test]
+    fn test_plugins() {
+        let config = Config::default();
+        assert!(config.plugins.is_empty());
+        assert_eq!(config.plugin_interval, Duration::from_secs(60));
+        assert_eq!(config.plugin_timeout, Duration::from_secs(10));
+
+        let config: Config = envy::from_iter([
+            (
+                "PLUGINS".to_owned(),
+                "/usr/local/bin/jenkins-busy,ip link show tun0 up".to_owned(),
+            ),
+            ("PLUGIN_INTERVAL".to_owned(), "30".to_owned()),
+            ("PLUGIN_TIMEOUT".to_owned(), "5".to_owned()),
+        ])
+        .unwrap();
+
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.plugins,
+            ["/usr/local/bin/jenkins-busy", "ip link show tun0 up"]
+        );
+        assert_eq!(config.plugin_interval, Duration::from_secs(30));
+        assert_eq!(config.plugin_timeout, Duration::from_secs(5));
+
+        // ----------------
+
+        let config = Config {
+            plugin_timeout: Duration::ZERO,
+            ..Default::default()
+        };
+
+        let result = config.validate();
+        assert!(result.is_err());
+
+        let result_err = result.unwrap_err();
+        let InvalidProperty { property, message } = result_err;
+        assert_eq!(property, "plugin_timeout");
+        assert_eq!(message, "plugin_timeout cannot be equal to zero");
+    }
+
+    #[test]
+    fn test_schedule() {
+        let config = Config::default();
+        assert_eq!(config.schedule, None);
+        assert_eq!(config.schedule_timezone, None);
+
+        let config: Config = envy::from_iter([
+            (
+                "SCHEDULE".to_owned(),
+                "Mon-Fri 08:30-17:30; Sat 10:00-12:00".to_owned(),
+            ),
+            ("SCHEDULE_TIMEZONE".to_owned(), "Europe/Warsaw".to_owned()),
+        ])
+        .unwrap();
+
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.schedule,
+            Some("Mon-Fri 08:30-17:30; Sat 10:00-12:00".parse().unwrap())
+        );
+        assert_eq!(config.schedule_timezone, Some(chrono_tz::Europe::Warsaw));
+
+        // ----------------
+
+        for (key, value) in [
+            ("SCHEDULE", "Mon-Fri"),
+            ("SCHEDULE", "Mon-Fri 9-17 Europe/Warsaw"),
+            ("SCHEDULE_TIMEZONE", "Europe/Gdansk"),
+        ] {
+            let config: Result<Config, _> = envy::from_iter([(key.to_owned(), value.to_owned())]);
+            assert!(config.is_err(), "{key}={value} should not be valid");
+        }
+    }
+
+    #[test]
+    fn test_calendar() {
+        let config = Config::default();
+        assert_eq!(config.calendar_ics, None);
+        assert_eq!(config.calendar_minutes_before, 5);
+        assert!(config.calendar_filters().summary.is_none());
+        assert!(config.calendar_filters().categories.is_empty());
+
+        let config: Config = envy::from_iter([
+            (
+                "CALENDAR_ICS".to_owned(),
+                "/home/user/calendar.ics".to_owned(),
+            ),
+            ("CALENDAR_MINUTES_BEFORE".to_owned(), "10".to_owned()),
+            ("CALENDAR_SUMMARY".to_owned(), "(?i)demo|review".to_owned()),
+            (
+                "CALENDAR_CATEGORIES".to_owned(),
+                "Meeting,Presentation".to_owned(),
+            ),
+        ])
+        .unwrap();
+
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.calendar_ics,
+            Some(PathBuf::from("/home/user/calendar.ics"))
+        );
+        assert_eq!(config.calendar_minutes_before, 10);
+        assert!(config.calendar_filters().summary.is_some());
+        assert_eq!(config.calendar_categories, ["Meeting", "Presentation"]);
+
+        // ----------------
+
+        let config = Config {
+            calendar_summary: Some("(demo".to_owned()),
+            ..Default::default()
+        };
+
+        let result = config.validate();
+        assert!(result.is_err());
+
+        let result_err = result.unwrap_err();
+        let InvalidProperty { property, message } = result_err;
+        assert_eq!(property, "calendar_summary");
+        assert_eq!(
+            message,
+            "calendar_summary must be a valid regular expression"
+        );
+    }
+
+    #[test]
+    fn test_keep_awake_while_process() {
+        let config: Config = envy::from_iter([(
+            "KEEP_AWAKE_WHILE_PROCESS".to_owned(),
+            r"rsync;ffmpeg;python3 .*train\.py;x{1,3}264".to_owned(),
+        )])
+        .unwrap();
+
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.keep_awake_while_process,
+            ["rsync", "ffmpeg", r"python3 .*train\.py", "x{1,3}264"]
+        );
+
+        // ----------------
+
+        let config: Config = envy::from_iter([(
+            "KEEP_AWAKE_WHILE_PROCESS".to_owned(),
+            "rsync;(ffmpeg".to_owned(),
+        )])
+        .unwrap();
+
+        let result = config.validate();
+        assert!(result.is_err());
+
+        let result_err = result.unwrap_err();
+        let InvalidProperty { property, message } = result_err;
+        assert_eq!(property, "keep_awake_while_process");
+        assert_eq!(
+            message,
+            "keep_awake_while_process must only contain valid regular expressions"
+        );
+    }
+
+    #[test]
+    fn test_stayawake_mode() {
+        let config = Config::default();
+        assert_eq!(config.stayawake_mode, Mode::Both);
+
+        let config: Config =
+            envy::from_iter([("STAYAWAKE_MODE".to_owned(), "lock-only".to_owned())]).unwrap();
+        assert_eq!(config.stayawake_mode, Mode::LockOnly);
+        assert!(config.stayawake_mode.locks());
+        assert!(!config.stayawake_mode.jiggles());
+
+        let config: Config =
+            envy::from_iter([("STAYAWAKE_MODE".to_owned(), "jiggle-only".to_owned())]).unwrap();
+        assert_eq!(config.stayawake_mode, Mode::JiggleOnly);
+        assert!(!config.stayawake_mode.locks());
+        assert!(config.stayawake_mode.jiggles());
+
+        let result =
+            envy::from_iter::<_, Config>([("STAYAWAKE_MODE".to_owned(), "sleep".to_owned())]);
+        assert!(result.is_err());
+    }
 }
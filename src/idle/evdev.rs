@@ -0,0 +1,360 @@
+//! Idle time measured from the events of the input devices (`/dev/input/event*`)
+//!
+//! Works without any display server (TTY, Wayland compositors without an idle protocol), but the user needs to be
+//! allowed to read the devices, usually by being a member of the `input` group.
+
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, ErrorKind, Read},
+    mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::{Path, PathBuf},
+    ptr,
+    sync::{Arc, Mutex, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use tracing::{debug, info};
+
+use super::IdleSource;
+use crate::config::Config;
+
+/// Directory of the input devices monitored when none are configured
+const INPUT_DIR: &str = "/dev/input";
+
+/// Key presses, including mouse buttons and touches of touchpads and touchscreens
+const EV_KEY: u16 = 0x01;
+/// Relative movements of mice and scroll wheels
+const EV_REL: u16 = 0x02;
+
+pub(crate) fn open(config: &Config) -> Result<Box<dyn IdleSource>> {
+    Ok(Box::new(Evdev::new(config.evdev_devices.clone())?))
+}
+
+/// Time of the last key or pointer event, shared with the threads reading the devices
+#[derive(Debug)]
+struct Activity {
+    last_event: Mutex<Instant>,
+    /// Devices being read
+    devices: Mutex<HashSet<PathBuf>>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Evdev {
+    /// Devices to read, every `event*` device of [`INPUT_DIR`] if empty
+    configured: Vec<PathBuf>,
+    activity: Arc<Activity>,
+    /// Read end of a pipe that hangs up once the source is dropped, so that the threads stop reading
+    stop: Arc<OwnedFd>,
+    /// Write end of the pipe, only closed on drop
+    _stop_writer: OwnedFd,
+}
+
+impl Evdev {
+    pub(crate) fn new(configured: Vec<PathBuf>) -> Result<Self> {
+        let (stop, stop_writer) = pipe().context("cannot create pipe")?;
+        let evdev = Self {
+            configured,
+            activity: Arc::new(Activity {
+                last_event: Mutex::new(Instant::now()),
+                devices: Mutex::new(HashSet::new()),
+            }),
+            stop: Arc::new(stop),
+            _stop_writer: stop_writer,
+        };
+
+        evdev.monitor_new_devices();
+
+        if evdev.activity.devices.lock().unwrap().is_empty() {
+            bail!("cannot read any input device (is the user a member of the `input` group?)");
+        }
+
+        Ok(evdev)
+    }
+
+    /// Starts reading the devices that are not read yet, including the ones plugged in since the previous call
+    fn monitor_new_devices(&self) {
+        let paths = if self.configured.is_empty() {
+            scan(Path::new(INPUT_DIR))
+        } else {
+            self.configured.clone()
+        };
+
+        let mut devices = self.activity.devices.lock().unwrap();
+
+        for path in paths {
+            if devices.contains(&path) {
+                continue;
+            }
+
+            match File::open(&path) {
+                Ok(file) => {
+                    debug!(device = %path.display(), "Monitoring input device");
+                    devices.insert(path.clone());
+
+                    let activity = Arc::downgrade(&self.activity);
+                    let stop = self.stop.clone();
+                    thread::spawn(move || read_events(file, &path, activity, &stop));
+                }
+                Err(err) => {
+                    debug!(device = %path.display(), error = %err, "Cannot read input device")
+                }
+            }
+        }
+    }
+}
+
+impl IdleSource for Evdev {
+    fn idle_time(&mut self) -> Result<Duration> {
+        self.monitor_new_devices();
+
+        if self.activity.devices.lock().unwrap().is_empty() {
+            bail!("all input devices are gone");
+        }
+
+        Ok(self.activity.last_event.lock().unwrap().elapsed())
+    }
+}
+
+/// Paths of the `event*` devices in `dir`
+fn scan(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("event"))
+        .map(|entry| entry.path())
+        .collect()
+}
+
+/// Reads `input_event` records until the device is gone or the source is dropped
+fn read_events(mut device: File, path: &Path, activity: Weak<Activity>, stop: &OwnedFd) {
+    let mut record = [0; mem::size_of::<libc::input_event>()];
+
+    let error = loop {
+        match wait_readable(&device, stop) {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!(device = %path.display(), "Not reading input device anymore");
+                return;
+            }
+            Err(err) => break err,
+        }
+
+        if let Err(err) = device.read_exact(&mut record) {
+            break err;
+        }
+
+        let Some(activity) = activity.upgrade() else {
+            return;
+        };
+
+        // Synchronization, LED, sound and absolute axis events (e.g. accelerometers) are not user activity
+        let event: libc::input_event = unsafe { ptr::read_unaligned(record.as_ptr().cast()) };
+        if matches!(event.type_, EV_KEY | EV_REL) {
+            *activity.last_event.lock().unwrap() = Instant::now();
+        }
+    };
+
+    if error.kind() == ErrorKind::UnexpectedEof {
+        debug!(device = %path.display(), "Input device closed");
+    } else {
+        info!(device = %path.display(), error = %error, "Input device removed");
+    }
+
+    if let Some(activity) = activity.upgrade() {
+        activity.devices.lock().unwrap().remove(path);
+    }
+}
+
+/// Pipe whose ends are closed on exec, read end first
+fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+/// Waits until the device can be read, returns false once `stop` hangs up
+fn wait_readable(device: &File, stop: &OwnedFd) -> io::Result<bool> {
+    let mut fds = [
+        libc::pollfd {
+            fd: device.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: stop.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+
+    loop {
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } >= 0 {
+            return Ok(fds[1].revents == 0);
+        }
+
+        let err = io::Error::last_os_error();
+        if err.kind() != ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CString, fs::OpenOptions, io::Write, os::unix::ffi::OsStrExt, slice};
+
+    use super::*;
+
+    const EV_SYN: u16 = 0x00;
+    const EV_ABS: u16 = 0x03;
+
+    fn record(type_: u16, code: u16, value: i32) -> Vec<u8> {
+        let event = libc::input_event {
+            time: libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            type_,
+            code,
+            value,
+        };
+        let bytes = unsafe {
+            slice::from_raw_parts(
+                (&event as *const libc::input_event).cast::<u8>(),
+                mem::size_of_val(&event),
+            )
+        };
+        bytes.to_vec()
+    }
+
+    fn mkfifo(path: &Path) {
+        let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o600) }, 0);
+    }
+
+    /// Waits until the idle time drops below `limit`, panics after a few seconds
+    fn wait_for_event(evdev: &mut Evdev, limit: Duration) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while evdev.idle_time().unwrap() >= limit {
+            assert!(Instant::now() < deadline, "event was not recorded");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_events_from_pipe() {
+        let dir = tempfile::tempdir().unwrap();
+        let fifo = dir.path().join("event0");
+        mkfifo(&fifo);
+
+        // Opening the write end for reading as well doesn't wait for a reader
+        let mut writer = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&fifo)
+            .unwrap();
+        let mut evdev = Evdev::new(vec![fifo]).unwrap();
+
+        thread::sleep(Duration::from_millis(200));
+        writer.write_all(&record(EV_SYN, 0, 0)).unwrap();
+        writer.write_all(&record(EV_ABS, 0, 512)).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(evdev.idle_time().unwrap() >= Duration::from_millis(300));
+
+        // Key press (KEY_A) followed by its synchronization
+        writer.write_all(&record(EV_KEY, 30, 1)).unwrap();
+        writer.write_all(&record(EV_SYN, 0, 0)).unwrap();
+        wait_for_event(&mut evdev, Duration::from_millis(100));
+
+        thread::sleep(Duration::from_millis(200));
+        writer.write_all(&record(EV_REL, 0, -3)).unwrap();
+        wait_for_event(&mut evdev, Duration::from_millis(100));
+
+        // The device is not read anymore once it is gone
+        drop(writer);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !evdev.activity.devices.lock().unwrap().is_empty() {
+            assert!(Instant::now() < deadline, "device was not removed");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_threads_stop_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let fifo = dir.path().join("event0");
+        mkfifo(&fifo);
+
+        // Never written to, the thread would wait for input forever
+        let _writer = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&fifo)
+            .unwrap();
+        let evdev = Evdev::new(vec![fifo]).unwrap();
+
+        let stop = evdev.stop.clone();
+        drop(evdev);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Arc::strong_count(&stop) > 1 {
+            assert!(Instant::now() < deadline, "thread is still reading");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_events_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let quiet = dir.path().join("event0");
+        let active = dir.path().join("event1");
+        fs::write(&quiet, record(EV_SYN, 0, 0)).unwrap();
+        fs::write(
+            &active,
+            [record(EV_KEY, 272, 1), record(EV_SYN, 0, 0)].concat(),
+        )
+        .unwrap();
+        fs::write(dir.path().join("mouse0"), record(EV_KEY, 272, 1)).unwrap();
+
+        let mut paths = scan(dir.path());
+        paths.sort();
+        assert_eq!(paths, [quiet.clone(), active.clone()]);
+
+        let activity = Arc::new(Activity {
+            last_event: Mutex::new(Instant::now() - Duration::from_secs(60)),
+            devices: Mutex::new(HashSet::from([quiet.clone(), active.clone()])),
+        });
+        let (stop, _stop_writer) = pipe().unwrap();
+
+        read_events(
+            File::open(&quiet).unwrap(),
+            &quiet,
+            Arc::downgrade(&activity),
+            &stop,
+        );
+        assert!(activity.last_event.lock().unwrap().elapsed() >= Duration::from_secs(60));
+
+        read_events(
+            File::open(&active).unwrap(),
+            &active,
+            Arc::downgrade(&activity),
+            &stop,
+        );
+        assert!(activity.last_event.lock().unwrap().elapsed() < Duration::from_secs(60));
+
+        // Both files ended, so they are not read anymore
+        assert!(activity.devices.lock().unwrap().is_empty());
+
+        assert!(Evdev::new(vec![dir.path().join("missing")]).is_err());
+    }
+}
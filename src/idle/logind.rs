@@ -0,0 +1,195 @@
+//! Idle time of the login session, as reported by systemd-logind
+
+use std::{
+    env,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use zbus::{
+    blocking::Connection,
+    dbus_proxy,
+    zvariant::{ObjectPath, OwnedObjectPath},
+    CacheProperties,
+};
+
+use super::IdleSource;
+use crate::config::Config;
+
+#[dbus_proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    fn get_session(&self, session_id: &str) -> zbus::Result<OwnedObjectPath>;
+
+    #[dbus_proxy(name = "GetSessionByPID")]
+    fn get_session_by_pid(&self, pid: u32) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1"
+)]
+trait Session {
+    #[dbus_proxy(property)]
+    fn idle_hint(&self) -> zbus::Result<bool>;
+
+    /// Microseconds since the epoch at which the idle hint last changed
+    #[dbus_proxy(property)]
+    fn idle_since_hint(&self) -> zbus::Result<u64>;
+}
+
+pub(crate) fn open(_config: &Config) -> Result<Box<dyn IdleSource>> {
+    let connection = Connection::system().context("failed to connect to the system bus")?;
+    let session_id = env::var("XDG_SESSION_ID").ok();
+    Ok(Box::new(Logind::with_connection(
+        &connection,
+        session_id.as_deref(),
+    )?))
+}
+
+/// Session of the current user, found by its ID or by the ID of this process
+#[derive(Debug)]
+pub(crate) struct Logind {
+    session: SessionProxyBlocking<'static>,
+}
+
+impl Logind {
+    fn with_connection(connection: &Connection, session_id: Option<&str>) -> Result<Self> {
+        let manager = ManagerProxyBlocking::new(connection)?;
+        let path = match session_id {
+            Some(id) => manager.get_session(id),
+            None => manager.get_session_by_pid(std::process::id()),
+        }
+        .context("failed to find the login session")?;
+
+        let session = SessionProxyBlocking::builder(connection)
+            .path(ObjectPath::from(path))?
+            // The hints are read on every check, caching them would only add a subscription
+            .cache_properties(CacheProperties::No)
+            .build()?;
+
+        let mut logind = Self { session };
+        logind.idle_time()?;
+        Ok(logind)
+    }
+}
+
+impl IdleSource for Logind {
+    fn idle_time(&mut self) -> Result<Duration> {
+        // Without an idle daemon the hint is never set, which would always look like activity
+        let since = self.session.idle_since_hint()?;
+        if since == 0 {
+            bail!("the session never reported whether it is idle");
+        }
+
+        if !self.session.idle_hint()? {
+            return Ok(Duration::ZERO);
+        }
+
+        let since = UNIX_EPOCH + Duration::from_micros(since);
+        Ok(SystemTime::now()
+            .duration_since(since)
+            .unwrap_or(Duration::ZERO))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use zbus::{dbus_interface, fdo};
+
+    use super::*;
+    use crate::test_bus::TestBus;
+
+    const SESSION_PATH: &str = "/org/freedesktop/login1/session/c1";
+
+    /// Stub of `org.freedesktop.login1.Manager` knowing a single session
+    struct StubManager;
+
+    #[dbus_interface(name = "org.freedesktop.login1.Manager")]
+    impl StubManager {
+        fn get_session(&self, session_id: String) -> fdo::Result<OwnedObjectPath> {
+            match session_id.as_str() {
+                "c1" => Ok(OwnedObjectPath::try_from(SESSION_PATH).unwrap()),
+                _ => Err(fdo::Error::Failed(format!("No session '{session_id}'"))),
+            }
+        }
+
+        #[dbus_interface(name = "GetSessionByPID")]
+        fn get_session_by_pid(&self, _pid: u32) -> fdo::Result<OwnedObjectPath> {
+            Ok(OwnedObjectPath::try_from(SESSION_PATH).unwrap())
+        }
+    }
+
+    /// Stub of `org.freedesktop.login1.Session`, the hints are shared with the test
+    struct StubSession {
+        hints: Arc<Mutex<(bool, u64)>>,
+    }
+
+    #[dbus_interface(name = "org.freedesktop.login1.Session")]
+    impl StubSession {
+        #[dbus_interface(property)]
+        fn idle_hint(&self) -> bool {
+            self.hints.lock().unwrap().0
+        }
+
+        #[dbus_interface(property)]
+        fn idle_since_hint(&self) -> u64 {
+            self.hints.lock().unwrap().1
+        }
+    }
+
+    #[test]
+    fn test_idle_time() {
+        let bus = TestBus::start();
+
+        let hints = Arc::new(Mutex::new((false, 0)));
+        let _service = bus
+            .builder()
+            .name("org.freedesktop.login1")
+            .unwrap()
+            .serve_at("/org/freedesktop/login1", StubManager)
+            .unwrap()
+            .serve_at(
+                SESSION_PATH,
+                StubSession {
+                    hints: hints.clone(),
+                },
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let connection = bus.connect();
+        assert!(Logind::with_connection(&connection, Some("c2")).is_err());
+
+        // The session never reported anything, the next source is tried instead
+        assert!(Logind::with_connection(&connection, Some("c1")).is_err());
+
+        let minute_ago = SystemTime::now() - Duration::from_secs(60);
+        let micros = minute_ago.duration_since(UNIX_EPOCH).unwrap().as_micros();
+        *hints.lock().unwrap() = (false, micros as u64);
+
+        assert!(Logind::with_connection(&connection, None).is_ok());
+
+        let mut logind = Logind::with_connection(&connection, Some("c1")).unwrap();
+        assert_eq!(logind.idle_time().unwrap(), Duration::ZERO);
+
+        *hints.lock().unwrap() = (true, micros as u64);
+
+        // Without the cache the new hints are read right away
+        let idle = logind.idle_time().unwrap();
+        assert!(idle >= Duration::from_secs(60) && idle < Duration::from_secs(70));
+    }
+
+    #[test]
+    fn test_idle_time_without_logind() {
+        let bus = TestBus::start();
+
+        assert!(Logind::with_connection(&bus.connect(), None).is_err());
+    }
+}
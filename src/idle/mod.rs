@@ -0,0 +1,84 @@
+//! Sources of the time since the user last used the keyboard or the pointer
+
+use std::{fmt::Debug, time::Duration};
+
+use anyhow::Result;
+use tracing::info;
+
+use crate::config::Config;
+
+#[cfg(target_os = "linux")]
+pub(crate) mod evdev;
+#[cfg(target_os = "linux")]
+pub(crate) mod logind;
+#[cfg(target_os = "linux")]
+pub(crate) mod x11;
+
+/// Time since the last input of the user
+pub(crate) trait IdleSource: Send + Debug {
+    fn idle_time(&mut self) -> Result<Duration>;
+}
+
+/// Named source of idle time
+pub(crate) struct Source {
+    pub(crate) name: &'static str,
+    open: fn(&Config) -> Result<Box<dyn IdleSource>>,
+}
+
+/// Sources available on Linux, in their default order
+#[cfg(target_os = "linux")]
+pub(crate) const SOURCES: &[Source] = &[
+    Source {
+        name: "x11",
+        open: x11::open,
+    },
+    Source {
+        name: "logind",
+        open: logind::open,
+    },
+    Source {
+        name: "evdev",
+        open: evdev::open,
+    },
+];
+
+/// No sources are available on other platforms yet
+#[cfg(not(target_os = "linux"))]
+pub(crate) const SOURCES: &[Source] = &[];
+
+/// Names of the sources available on the current platform, in their default order
+pub(crate) fn source_names() -> impl Iterator<Item = &'static str> {
+    SOURCES.iter().map(|source| source.name)
+}
+
+/// Opens the first available source, tried in the order of `idle_sources` or in the platform's default order if it is empty
+pub(crate) fn open(config: &Config) -> Option<Box<dyn IdleSource>> {
+    let order = &config.idle_sources;
+    let sources: Vec<&Source> = if order.is_empty() {
+        SOURCES.iter().collect()
+    } else {
+        // Names are validated with the configuration
+        order
+            .iter()
+            .filter_map(|name| SOURCES.iter().find(|source| source.name == name))
+            .collect()
+    };
+
+    for source in sources {
+        match (source.open)(config) {
+            Ok(opened) => {
+                info!(source = source.name, "Measuring idle time");
+                return Some(opened);
+            }
+            Err(err) => {
+                info!(
+                    source = source.name,
+                    error = format!("{err:#}"),
+                    "Idle time source unavailable"
+                )
+            }
+        }
+    }
+
+    None
+}
@@ -0,0 +1,148 @@
+//! Idle time of the X11 server, as reported by the MIT-SCREEN-SAVER extension
+
+use std::{ffi::CStr, ptr, time::Duration};
+
+use anyhow::{bail, Result};
+use x11_dl::{
+    xlib::{Display, Xlib},
+    xss::{XScreenSaverInfo, Xss},
+};
+
+use super::IdleSource;
+use crate::config::Config;
+
+pub(crate) fn open(_config: &Config) -> Result<Box<dyn IdleSource>> {
+    Ok(Box::new(X11::new(None)?))
+}
+
+/// Connection to the X11 server, the libraries are loaded at runtime so that they are not required
+pub(crate) struct X11 {
+    xlib: Xlib,
+    xss: Xss,
+    display: *mut Display,
+    info: *mut XScreenSaverInfo,
+}
+
+// The display connection is only ever used by its owner
+unsafe impl Send for X11 {}
+
+impl std::fmt::Debug for X11 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("X11")
+            .field("display", &self.display)
+            .finish()
+    }
+}
+
+impl X11 {
+    /// Connects to the given display, or to `DISPLAY`
+    pub(crate) fn new(display_name: Option<&CStr>) -> Result<Self> {
+        let xlib = Xlib::open()?;
+        let xss = Xss::open()?;
+
+        let display =
+            unsafe { (xlib.XOpenDisplay)(display_name.map_or(ptr::null(), CStr::as_ptr)) };
+        if display.is_null() {
+            bail!("cannot open X11 display");
+        }
+
+        let (mut event_base, mut error_base) = (0, 0);
+        if unsafe { (xss.XScreenSaverQueryExtension)(display, &mut event_base, &mut error_base) }
+            == 0
+        {
+            unsafe { (xlib.XCloseDisplay)(display) };
+            bail!("the X11 server has no MIT-SCREEN-SAVER extension");
+        }
+
+        let info = unsafe { (xss.XScreenSaverAllocInfo)() };
+        if info.is_null() {
+            unsafe { (xlib.XCloseDisplay)(display) };
+            bail!("cannot allocate screen saver information");
+        }
+
+        Ok(Self {
+            xlib,
+            xss,
+            display,
+            info,
+        })
+    }
+}
+
+impl IdleSource for X11 {
+    fn idle_time(&mut self) -> Result<Duration> {
+        let status = unsafe {
+            let root = (self.xlib.XDefaultRootWindow)(self.display);
+            (self.xss.XScreenSaverQueryInfo)(self.display, root, self.info)
+        };
+
+        if status == 0 {
+            bail!("cannot query screen saver information");
+        }
+
+        Ok(Duration::from_millis(unsafe { (*self.info).idle }))
+    }
+}
+
+impl Drop for X11 {
+    fn drop(&mut self) {
+        unsafe {
+            (self.xlib.XFree)(self.info.cast());
+            (self.xlib.XCloseDisplay)(self.display);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::CString,
+        path::Path,
+        process::{Child, Command, Stdio},
+        thread,
+        time::Instant,
+    };
+
+    use super::*;
+
+    /// An `Xvfb` server, killed on drop
+    struct Xvfb(Child);
+
+    impl Drop for Xvfb {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    #[test]
+    #[ignore = "needs Xvfb, run with `cargo test -- --ignored`"]
+    fn test_idle_time_under_xvfb() {
+        let number = 90 + std::process::id() % 100;
+        let xvfb = Xvfb(
+            Command::new("Xvfb")
+                .arg(format!(":{number}"))
+                .args(["-nolisten", "tcp"])
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("cannot start Xvfb"),
+        );
+
+        let socket = format!("/tmp/.X11-unix/X{number}");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !Path::new(&socket).exists() {
+            assert!(Instant::now() < deadline, "Xvfb did not start");
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        let display = CString::new(format!(":{number}")).unwrap();
+        let mut x11 = X11::new(Some(&display)).unwrap();
+
+        let idle = x11.idle_time().unwrap();
+        thread::sleep(Duration::from_millis(300));
+        assert!(x11.idle_time().unwrap() >= idle + Duration::from_millis(200));
+
+        drop(x11);
+        drop(xvfb);
+    }
+}
@@ -0,0 +1,340 @@
+use crate::{
+    config::Config,
+    idle::{self, IdleSource},
+    offset_generator::OffsetGenerator,
+};
+use anyhow::Result;
+use mouse_rs::{types::Point, Mouse};
+use rand::prelude::ThreadRng;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, trace, warn};
+
+/// Whether the pointer moved farther than `deadzone` pixels, smaller displacements are sensor jitter
+fn moved(from: (i32, i32), to: (i32, i32), deadzone: u32) -> bool {
+    let dx = i128::from(to.0) - i128::from(from.0);
+    let dy = i128::from(to.1) - i128::from(from.1);
+    dx * dx + dy * dy > i128::from(deadzone) * i128::from(deadzone)
+}
+
+/// Pointer position and the last time it was moved by the user, used when the idle time cannot be measured
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Pointer {
+    position: (i32, i32),
+    moved_at: Instant,
+}
+
+impl Pointer {
+    /// Nothing is known about earlier movements, the pointer is only tracked from now on
+    fn new(position: (i32, i32), now: Instant) -> Self {
+        Self {
+            position,
+            moved_at: now,
+        }
+    }
+
+    /// Records a sample of the position, returns whether the pointer moved since the last movement
+    ///
+    /// Jitter within the deadzone is measured from where the pointer last moved to, so it never adds up.
+    fn sample(&mut self, position: (i32, i32), now: Instant, deadzone: u32) -> bool {
+        if !moved(self.position, position, deadzone) {
+            return false;
+        }
+
+        self.position = position;
+        self.moved_at = now;
+        true
+    }
+}
+
+/// Idle time accumulated across checks, until it reaches the threshold
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Idleness {
+    /// How long the user has to be idle before the mouse is moved
+    threshold: Duration,
+    /// How often the checks run
+    interval: Duration,
+    /// When the mouse was last moved by us, which the system counts as activity as well
+    jiggled_at: Option<Instant>,
+}
+
+impl Idleness {
+    fn new(config: &Config) -> Self {
+        Self {
+            threshold: config.idle_threshold(),
+            interval: config.stayawake_interval,
+            jiggled_at: None,
+        }
+    }
+
+    /// Idle time since the last input of the user or our own last movement, whichever is later
+    fn idle_time(&self, user_idle: Duration, now: Instant) -> Duration {
+        match self.jiggled_at {
+            Some(jiggled_at) => user_idle.min(now.saturating_duration_since(jiggled_at)),
+            None => user_idle,
+        }
+    }
+
+    fn reached(&self, idle: Duration) -> bool {
+        idle >= self.threshold
+    }
+
+    /// Time until the threshold is reached if it is before the next check, which runs on a timer and can be a bit early
+    fn recheck_in(&self, idle: Duration) -> Option<Duration> {
+        let wait = self.threshold.checked_sub(idle)?;
+        (!wait.is_zero() && wait < self.interval).then_some(wait)
+    }
+}
+
+/// Moves the mouse whenever the user has been idle for long enough
+///
+/// Idle time is measured by the system if possible, otherwise it is the time since the pointer was last seen moving.
+pub(crate) struct Jiggler {
+    mouse: Mouse,
+    offset_gen: OffsetGenerator<ThreadRng>,
+    idle: Option<Box<dyn IdleSource>>,
+    idleness: Idleness,
+    /// Displacement in pixels up to which the pointer is not considered moved, only when tracking it without an idle
+    /// source
+    deadzone: u32,
+    /// Pointer tracked since the first sample
+    pointer: Option<Pointer>,
+}
+
+impl Jiggler {
+    pub(crate) fn new(config: Config) -> Result<Self> {
+        // Without a display the mouse handle cannot be used at all
+        #[cfg(target_os = "linux")]
+        if std::env::var_os("DISPLAY").is_none() {
+            anyhow::bail!(
+                "No X11 display available to move the mouse on (try STAYAWAKE_MODE=lock-only)"
+            );
+        }
+
+        let idle = idle::open(&config);
+
+        // The system only tells how long ago the last input was, not how far the mouse moved
+        if idle.is_some() && config.movement_deadzone_px > 0 {
+            warn!(
+                deadzone = config.movement_deadzone_px,
+                "MOVEMENT_DEADZONE_PX only applies when tracking mouse movements, the measured idle time counts any movement"
+            );
+        }
+
+        Ok(Self {
+            mouse: Mouse::new(),
+            idle,
+            idleness: Idleness::new(&config),
+            deadzone: config.movement_deadzone_px,
+            offset_gen: OffsetGenerator::new(config),
+            pointer: None,
+        })
+    }
+
+    /// Forgets the tracked pointer and the accumulated idle time, the next sample starts tracking them again
+    pub(crate) fn reset(&mut self) {
+        self.pointer = None;
+        self.idleness.jiggled_at = None;
+    }
+
+    /// Samples the pointer position between checks, unless the idle time is measured by the system
+    pub(crate) fn sample(&mut self) {
+        if self.idle.is_none() {
+            self.sample_pointer();
+        }
+    }
+
+    /// Moves the mouse if the user has been idle for at least the threshold
+    ///
+    /// Returns when to check again if the threshold is reached before the next check.
+    pub(crate) fn check(&mut self) -> Option<Duration> {
+        trace!("Check start");
+
+        let user_idle = match self.idle.as_mut().map(|source| source.idle_time()) {
+            Some(Ok(idle)) => idle,
+            Some(Err(err)) => {
+                warn!(error = %err, "Cannot measure idle time, tracking mouse movements instead");
+                self.idle = None;
+                self.sample_pointer();
+                return None;
+            }
+            None => self.sample_pointer()?.moved_at.elapsed(),
+        };
+
+        let idle = self.idleness.idle_time(user_idle, Instant::now());
+        if !self.idleness.reached(idle) {
+            trace!(?idle, "User is active, not moving mouse");
+            return self.idleness.recheck_in(idle);
+        }
+
+        debug!(?idle, "User is idle, moving mouse");
+
+        let from = match self.mouse.get_position() {
+            Ok(pos) => pos,
+            Err(err) => {
+                error!(error = ?err, "Cannot get mouse position");
+                return None;
+            }
+        };
+        self.jiggle(&from);
+        self.idleness.jiggled_at = Some(Instant::now());
+
+        let to = match self.mouse.get_position() {
+            Ok(pos) => (pos.x, pos.y),
+            Err(err) => {
+                error!(error = ?err, "Cannot get mouse position");
+                self.pointer = None;
+                return None;
+            }
+        };
+
+        if !moved((from.x, from.y), to, self.deadzone) {
+            warn!(
+                ?from,
+                "The mouse did not move, it may not be allowed to on this display"
+            );
+        }
+
+        // Our own movement doesn't make the user any less idle
+        if let Some(pointer) = self.pointer.as_mut() {
+            pointer.position = to;
+        }
+
+        None
+    }
+
+    /// Measures the pointer position, returns the tracked pointer unless the position cannot be measured
+    fn sample_pointer(&mut self) -> Option<Pointer> {
+        let pos = match self.mouse.get_position() {
+            Ok(pos) => pos,
+            Err(err) => {
+                error!(error = ?err, "Cannot get mouse position");
+                self.pointer = None;
+                return None;
+            }
+        };
+
+        let now = Instant::now();
+        let position = (pos.x, pos.y);
+
+        match self.pointer.as_mut() {
+            Some(pointer) => {
+                if pointer.sample(position, now, self.deadzone) {
+                    trace!(?pos, "Movement detected");
+                }
+            }
+            None => self.pointer = Some(Pointer::new(position, now)),
+        }
+
+        self.pointer
+    }
+
+    /// Moves the mouse to a random position around `from`
+    fn jiggle(&mut self, from: &Point) {
+        let new_pos = self.offset_gen.get_random_offset_position(from);
+
+        trace!("Moving mouse from {:?} to {:?}", from, &new_pos);
+
+        if let Err(err) = self.mouse.move_to(new_pos.x, new_pos.y) {
+            error!(error = ?err, "Cannot move the mouse to a new position");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pointer_sampling() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        let mut pointer = Pointer::new((10, 10), at(1000));
+        assert_eq!(pointer.moved_at, at(1000));
+
+        // Leaving and coming back to the same pixel between checks still counts as a movement
+        assert!(pointer.sample((12, 10), at(1500), 0));
+        assert!(pointer.sample((10, 10), at(2000), 0));
+        assert_eq!(pointer.moved_at, at(2000));
+
+        assert!(!pointer.sample((10, 10), at(2500), 0));
+        assert!(!pointer.sample((10, 10), at(3000), 0));
+        assert_eq!(pointer.moved_at, at(2000));
+        assert_eq!(pointer.position, (10, 10));
+    }
+
+    #[test]
+    fn test_deadzone() {
+        assert!(!moved((10, 10), (10, 10), 0));
+        assert!(moved((10, 10), (11, 10), 0));
+        assert!(!moved((10, 10), (12, 12), 3));
+        assert!(moved((10, 10), (13, 13), 3));
+        assert!(moved((i32::MIN, i32::MIN), (i32::MAX, i32::MAX), u32::MAX));
+
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut pointer = Pointer::new((100, 100), at(0));
+
+        // Jitter around the same spot never adds up to a movement
+        for (i, position) in [(101, 100), (102, 101), (100, 102), (98, 99), (102, 98)]
+            .into_iter()
+            .enumerate()
+        {
+            assert!(!pointer.sample(position, at(500 * (i as u64 + 1)), 3));
+        }
+        assert_eq!(pointer.moved_at, at(0));
+        assert_eq!(pointer.position, (100, 100));
+
+        assert!(pointer.sample((104, 100), at(3000), 3));
+        assert_eq!(pointer.moved_at, at(3000));
+    }
+
+    #[test]
+    fn test_idleness() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // By default the mouse is moved at every check once the user is idle, a check a bit early checks again
+        let mut idleness = Idleness::new(&Config::default());
+        assert!(!idleness.reached(Duration::from_millis(7_500)));
+        assert!(!idleness.reached(Duration::from_millis(14_990)));
+        assert!(idleness.reached(Duration::from_secs(15)));
+        assert_eq!(
+            idleness.recheck_in(Duration::from_millis(14_990)),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(idleness.recheck_in(Duration::ZERO), None);
+        assert_eq!(idleness.recheck_in(Duration::from_secs(15)), None);
+
+        idleness.jiggled_at = Some(at(15));
+        let idle = idleness.idle_time(Duration::from_secs(30), at(30) - Duration::from_millis(10));
+        assert!(!idleness.reached(idle));
+        assert!(idleness.reached(idleness.idle_time(Duration::from_secs(30), at(30))));
+
+        // Checking every 5 seconds, but only moving the mouse after 4 minutes
+        let mut idleness = Idleness::new(&Config {
+            stayawake_interval: Duration::from_secs(5),
+            idle_threshold: Some(Duration::from_secs(240)),
+            ..Default::default()
+        });
+        assert!(!idleness.reached(idleness.idle_time(Duration::from_secs(235), at(235))));
+        assert!(!idleness.reached(Duration::from_millis(237_500)));
+        assert!(idleness.reached(idleness.idle_time(Duration::from_secs(240), at(240))));
+        assert_eq!(
+            idleness.recheck_in(Duration::from_secs(237)),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(idleness.recheck_in(Duration::from_secs(235)), None);
+
+        // The user is still idle, but the system counted our movement as activity
+        idleness.jiggled_at = Some(at(240));
+        assert!(!idleness.reached(idleness.idle_time(Duration::from_secs(300), at(300))));
+        assert!(idleness.reached(idleness.idle_time(Duration::from_secs(480), at(480))));
+
+        // Activity of the user counts from whenever it happened
+        assert_eq!(
+            idleness.idle_time(Duration::from_secs(10), at(480)),
+            Duration::from_secs(10)
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Keeping the system awake according to the configured mode
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tracing::{debug, info, trace, trace_span, warn};
+
+use crate::{
+    config::Config,
+    jiggler::Jiggler,
+    models::{Decision, Mode},
+    power,
+};
+
+/// Holds the power management lock and moves the mouse as decided by the policy
+pub(crate) struct Keeper {
+    config: Config,
+    mode: Mode,
+    /// Whether a failure to acquire the power management lock is fatal
+    lock_required: bool,
+    inhibitor: power::Inhibitor,
+    /// Whether the lock is held under the current decision
+    locking: bool,
+    /// Whether the last attempt to acquire the lock failed, it is tried again on every tick
+    lock_failed: bool,
+    jiggler: Option<Jiggler>,
+    decision: Decision,
+    /// Whether the mouse is moved under the current decision
+    jiggling: bool,
+}
+
+impl Keeper {
+    pub(crate) fn new(config: &Config) -> Result<Self> {
+        let mode = config.stayawake_mode;
+        let jiggler = if mode.jiggles() {
+            Some(Jiggler::new(config.clone())?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            mode,
+            lock_required: config.power_lock_required,
+            inhibitor: power::Inhibitor::new(
+                config.power_backends.clone(),
+                config.power_lock_request(),
+            ),
+            locking: false,
+            lock_failed: false,
+            jiggler,
+            // Nothing is held until the first decision
+            decision: Decision::Paused,
+            jiggling: false,
+        })
+    }
+
+    /// Starts, changes or stops keeping the system awake, `mode` is the configured one unless a condition overrides it
+    pub(crate) fn apply(&mut self, decision: Decision, mode: Mode) -> Result<()> {
+        if self.decision == decision && self.mode == mode {
+            return Ok(());
+        }
+
+        let (locks, mut jiggles) = match decision {
+            Decision::Active => (mode.locks(), mode.jiggles()),
+            Decision::LockOnly => (true, false),
+            Decision::Paused => (false, false),
+        };
+
+        match decision {
+            Decision::Active => info!(?mode, "Keeping the system awake"),
+            Decision::LockOnly if self.decision == decision => {}
+            Decision::LockOnly => {
+                info!("Keeping the system awake with the power management lock only")
+            }
+            Decision::Paused if self.decision == decision => {}
+            Decision::Paused => info!("Not keeping the system awake anymore"),
+        }
+
+        self.decision = decision;
+        self.mode = mode;
+
+        // Only needed when a condition asks for mouse movements the configured mode doesn't make
+        if jiggles && self.jiggler.is_none() {
+            match Jiggler::new(self.config.clone()) {
+                Ok(jiggler) => self.jiggler = Some(jiggler),
+                Err(err) => {
+                    warn!(error = %err, "Cannot move the mouse");
+                    jiggles = false;
+                }
+            }
+        }
+
+        self.jiggling = jiggles;
+
+        if !jiggles {
+            if let Some(jiggler) = self.jiggler.as_mut() {
+                jiggler.reset();
+            }
+        }
+
+        self.locking = locks;
+
+        if locks {
+            self.acquire_lock()
+        } else {
+            self.inhibitor.release();
+            self.lock_failed = false;
+            Ok(())
+        }
+    }
+
+    /// Acquires the lock, a failure is only fatal if the lock is required, otherwise it is tried again later
+    fn acquire_lock(&mut self) -> Result<()> {
+        let power_lock_span = trace_span!("power_lock");
+        let _power_lock_span_entered = power_lock_span.enter();
+
+        let result = self.inhibitor.acquire();
+        trace!(?result, "Inhibiting Power Management");
+
+        match result {
+            Ok(()) => {
+                self.lock_failed = false;
+                Ok(())
+            }
+            Err(err) if self.lock_required => Err(err).context("Cannot inhibit Power Management"),
+            // Only the first failure in a row is worth a warning
+            Err(err) if self.lock_failed => {
+                debug!(error = %err, "Cannot inhibit Power Management yet");
+                Ok(())
+            }
+            Err(err) => {
+                if self.jiggling {
+                    warn!(error = %err, "Cannot inhibit Power Management, relying on mouse movements until it can");
+                } else {
+                    warn!(error = %err, "Cannot inhibit Power Management, trying again on every check");
+                }
+                self.lock_failed = true;
+                Ok(())
+            }
+        }
+    }
+
+    /// Acquires the lock if an earlier attempt failed and moves the mouse if needed, called on every tick of the main
+    /// loop
+    ///
+    /// Returns when to check again if the mouse has to be moved before the next tick.
+    pub(crate) fn tick(&mut self) -> Result<Option<Duration>> {
+        if self.locking && self.lock_failed {
+            self.acquire_lock()?;
+        }
+
+        if !self.jiggling {
+            return Ok(None);
+        }
+
+        Ok(self.jiggler.as_mut().and_then(Jiggler::check))
+    }
+
+    /// Samples the mouse position between ticks, only while jiggling
+    pub(crate) fn sample(&mut self) {
+        if !self.jiggling {
+            return;
+        }
+
+        if let Some(jiggler) = self.jiggler.as_mut() {
+            jiggler.sample();
+        }
+    }
+
+    /// Changes the application name and reason of the power management lock
+    pub(crate) fn set_reason(&mut self, who: String, reason: String) -> Result<(), power::Error> {
+        self.inhibitor.set_reason(who, reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Configuration whose lock can never be acquired
+    fn lock_only(lock_required: bool) -> Config {
+        Config {
+            stayawake_mode: Mode::LockOnly,
+            power_backends: vec!["unknown".to_owned()],
+            power_lock_required: lock_required,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lock_failure_is_retried() {
+        let mut keeper = Keeper::new(&lock_only(false)).unwrap();
+
+        assert!(keeper.apply(Decision::Active, Mode::LockOnly).is_ok());
+        assert!(keeper.locking && keeper.lock_failed);
+
+        // The decision doesn't change, but the lock is still tried again
+        assert!(keeper.tick().is_ok());
+        assert!(keeper.lock_failed);
+
+        assert!(keeper.apply(Decision::Paused, Mode::LockOnly).is_ok());
+        assert!(!keeper.locking && !keeper.lock_failed);
+
+        assert!(keeper.apply(Decision::LockOnly, Mode::LockOnly).is_ok());
+        assert!(keeper.lock_failed);
+    }
+
+    #[test]
+    fn test_lock_failure_is_fatal_if_required() {
+        let mut keeper = Keeper::new(&lock_only(true)).unwrap();
+
+        assert!(keeper.apply(Decision::Active, Mode::LockOnly).is_err());
+    }
+}
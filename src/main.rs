@@ -3,27 +3,70 @@
 //!
 //! As with the Python package the program is only triggered when you don't do any mouse movements and it is completely headless (it is intended to be used as a command line tool).
 
+mod child;
+mod cli;
+mod conditions;
 mod config;
+mod idle;
+mod jiggler;
+mod keeper;
 mod models;
 mod offset_generator;
+mod policy;
 mod power;
+mod signals;
+#[cfg(all(test, target_os = "linux"))]
+mod test_bus;
+mod watch;
 
-use crate::{config::Config, offset_generator::OffsetGenerator};
-use anyhow::Result;
+use crate::{
+    child::ChildCommand,
+    cli::{Cli, Command, PolicyAction},
+    config::Config,
+    keeper::Keeper,
+    policy::Policy,
+    signals::{Event, Signals},
+    watch::PidWatcher,
+};
+use anyhow::{Context, Result};
+use clap::Parser;
 use dotenv::dotenv;
-use mouse_rs::Mouse;
-use tokio::time;
-use tracing::{debug, error, error_span, info, trace, trace_span};
+use std::{
+    collections::HashMap,
+    env, future, io,
+    process::{ExitCode, ExitStatus},
+    time::Duration,
+};
+use tokio::time::{self, Instant, Interval, MissedTickBehavior};
+use tracing::{debug, error, error_span, info};
 use tracing_subscriber::EnvFilter;
 
+/// Longest time between two checks, the monotonic clock of timers doesn't advance while the system is suspended
+const MAX_SLEEP: Duration = Duration::from_secs(300);
+
+/// How often `policy explain` checks whether the conditions running in the background are done
+const EXPLAIN_POLL: Duration = Duration::from_millis(50);
+
+/// Time for a timed out plugin to be killed and reported, on top of its timeout
+const EXPLAIN_GRACE: Duration = Duration::from_secs(1);
+
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+
+    // Variables set in the environment take precedence over the .env file, also when reloading it
+    let process_env: HashMap<String, String> = env::vars().collect();
     dotenv().ok();
 
     let filter_layer = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("INFO"))?;
-    tracing_subscriber::fmt()
-        .with_env_filter(filter_layer)
-        .init();
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter_layer);
+
+    // Keep the output of commands apart from the logs
+    if cli.subcommand.is_some() {
+        subscriber.with_writer(io::stderr).init();
+    } else {
+        subscriber.init();
+    }
 
     let init_span = error_span!("initialization");
     let init_span_entered = init_span.enter();
@@ -39,72 +82,179 @@ async fn main() -> Result<()> {
 
     debug!(?config);
 
-    let mouse = Mouse::new();
-    let mut interval = time::interval(config.stayawake_interval);
-    let mut offset_gen = OffsetGenerator::new(config);
-
-    info!("Initialization finished successfully");
-    drop(init_span_entered);
+    if let Some(Command::Policy {
+        action: PolicyAction::Explain,
+    }) = cli.subcommand
+    {
+        let mut policy = Policy::from_config(&config)?;
+        policy.decide();
 
-    let get_pos_err = |err| error!(error = ?err, "Cannot get mouse position");
+        // Plugins run in the background, their first run is waited for as long as it can take
+        let deadline = Instant::now() + config.plugin_timeout + EXPLAIN_GRACE;
+        while policy.pending() && Instant::now() < deadline {
+            time::sleep(EXPLAIN_POLL).await;
+            policy.decide();
+        }
 
-    interval.tick().await; // Initial tick is instant
+        print!("{}", policy.explain());
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    // Create Power Manager lock
-    {
-        let power_lock_span = trace_span!("power_lock");
-        let _power_lock_span_entered = power_lock_span.enter();
+    let mode = config.stayawake_mode;
+    let mut interval = time::interval(config.stayawake_interval);
+    let mut sampler = config.sample_every().map(|period| {
+        let mut sampler = time::interval(period);
+        sampler.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        sampler
+    });
+    let mut keeper = Keeper::new(&config)?;
+    let mut policy = Policy::from_config(&config)?;
+    let mut signals = Signals::new()?;
 
-        let lock = power::lock();
-        trace!(result = ?lock, "Inhibiting Power Management");
+    let mut watcher = PidWatcher::new(&cli.watch_pids);
+    if !cli.watch_pids.is_empty() && !watcher.any_alive() {
+        info!("None of the watched processes is running, nothing to do");
+        return Ok(ExitCode::SUCCESS);
     }
 
+    info!(?mode, "Initialization finished successfully");
+    drop(init_span_entered);
+
+    update(&mut keeper, &mut policy, &config, &mut interval)?;
+
+    // The command is started only once the system is kept awake
+    let mut child = if cli.command.is_empty() {
+        None
+    } else {
+        let child = ChildCommand::spawn(&cli.command)
+            .with_context(|| format!("Cannot start command {:?}", cli.command[0]))?;
+        info!(program = ?child.program(), pid = child.id(), "Command started");
+        Some(child)
+    };
+
     let loop_span = error_span!("main_loop");
     let _loop_span_entered = loop_span.enter();
 
-    loop {
-        trace!("Loop start");
+    // Extra check when the idle threshold is reached between two ticks
+    let mut recheck_at = None;
 
-        // Grab mouse position
-        let pos1 = match mouse.get_position() {
-            Ok(pos) => pos,
-            Err(err) => {
-                get_pos_err(err);
-                continue;
+    let exit_code = loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                update(&mut keeper, &mut policy, &config, &mut interval)?;
+                recheck_at = keeper.tick()?.map(|wait| Instant::now() + wait);
+            }
+            _ = sleep_until(recheck_at) => {
+                recheck_at = keeper.tick()?.map(|wait| Instant::now() + wait);
             }
-        };
-
-        // Wait
-        trace!("Tick started");
-        interval.tick().await;
-        trace!("Tick completed");
-
-        // Measure mouse position again
-        let pos2 = match mouse.get_position() {
-            Ok(pos) => pos,
-            Err(err) => {
-                get_pos_err(err);
-                continue;
+            _ = tick(&mut sampler) => keeper.sample(),
+            status = wait_child(&mut child) => {
+                let status = status.context("Cannot wait for the command to exit")?;
+                info!(%status, "Command exited");
+                break child::exit_code(status);
             }
-        };
+            pid = watcher.wait() => {
+                info!(pid, "Last watched process exited");
+                break ExitCode::SUCCESS;
+            }
+            event = signals.recv() => match (event, &child) {
+                // The command decides when to exit, StayAwake waits for it, and `SIGHUP` is meant for it as well
+                #[cfg(unix)]
+                (event, Some(child)) => {
+                    if child.got_from_terminal(event.signal()) {
+                        debug!(signal = event.signal(), "The command got the signal from the terminal");
+                    } else {
+                        debug!(signal = event.signal(), "Forwarding signal to the command");
+                        if let Err(err) = child.forward(event.signal()) {
+                            error!(error = %err, "Cannot forward signal to the command");
+                        }
+                    }
+                }
+                // Ctrl+C is delivered to the whole console, including the command
+                #[cfg(not(unix))]
+                (_, Some(_)) => {}
+                (Event::Reload, None) => reload_power_lock(&mut keeper, &process_env),
+                (Event::Interrupt | Event::Terminate, None) => break ExitCode::SUCCESS,
+            },
+        }
+    };
 
-        trace!(?pos1, ?pos2, "Interval position results");
+    info!("Shutting down");
 
-        // If position didn't change during the last interval, move the mouse now
-        if pos1.x == pos2.x && pos1.y == pos2.y {
-            let new_pos = offset_gen.get_random_offset_position(&pos1);
+    Ok(exit_code)
+}
 
-            trace!(
-                "Movement not detected, moving mouse from {:?} to {:?}",
-                &pos1,
-                &new_pos
-            );
+/// Applies the decision of the policy, with the check interval and mode overridden by its conditions if any
+fn update(
+    keeper: &mut Keeper,
+    policy: &mut Policy,
+    config: &Config,
+    interval: &mut Interval,
+) -> Result<()> {
+    let decision = policy.decide();
+    let overrides = policy.overrides();
 
-            if let Err(err) = mouse.move_to(new_pos.x, new_pos.y) {
-                error!(error = ?err, "Cannot move the mouse to a new position");
-            }
-        } else {
-            trace!("Movement detected, not moving mouse");
+    keeper.apply(decision, overrides.mode.unwrap_or(config.stayawake_mode))?;
+
+    let period = overrides.interval.unwrap_or(config.stayawake_interval);
+    let now = Instant::now();
+
+    // Nothing changes before a rule can match again, e.g. until the next schedule window starts
+    let wake_at = policy
+        .holds_again_at()
+        .map(|at| Instant::from_std(at).min(now + MAX_SLEEP))
+        .filter(|at| *at > now + period);
+
+    if let Some(at) = wake_at {
+        debug!(duration = ?(at - now), "Sleeping until the policy can match again");
+        *interval = time::interval_at(at, period);
+    } else if period != interval.period() {
+        info!(?period, "Check interval changed");
+        *interval = time::interval_at(now + period, period);
+    }
+
+    Ok(())
+}
+
+/// Waits for the next tick of an optional timer, never completes if there is no timer
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
         }
+        None => future::pending().await,
+    }
+}
+
+/// Waits until an optional deadline, never completes if there is no deadline
+async fn sleep_until(at: Option<Instant>) {
+    match at {
+        Some(at) => time::sleep_until(at).await,
+        None => future::pending().await,
+    }
+}
+
+/// Waits for the command to exit, never completes if there is no command
+async fn wait_child(child: &mut Option<ChildCommand>) -> io::Result<ExitStatus> {
+    match child {
+        Some(child) => child.wait().await,
+        None => future::pending().await,
+    }
+}
+
+/// Applies the power lock reason and application name of the reloaded configuration
+fn reload_power_lock(keeper: &mut Keeper, process_env: &HashMap<String, String>) {
+    info!("Reloading configuration");
+
+    let config = match Config::reload(process_env) {
+        Ok(config) => config,
+        Err(err) => {
+            error!(error = %err, "Cannot reload configuration");
+            return;
+        }
+    };
+
+    if let Err(err) = keeper.set_reason(config.power_lock_who, config.power_lock_reason) {
+        error!(error = %err, "Cannot update Power Management lock");
     }
 }
@@ -1,20 +1,95 @@
-use std::num::ParseIntError;
+use std::{fmt, num::ParseIntError, str::FromStr};
 
 use anyhow::{anyhow, Result};
 use serde::{de::Error, Deserialize, Deserializer};
 
-#[derive(PartialEq, Debug)]
+/// What StayAwake does to keep the system awake
+#[derive(Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Mode {
+    /// Only hold the power management lock
+    LockOnly,
+    /// Only move the mouse
+    JiggleOnly,
+    /// Hold the power management lock and move the mouse
+    Both,
+}
+
+impl Mode {
+    /// Whether the power management lock is held
+    pub(crate) fn locks(self) -> bool {
+        matches!(self, Mode::LockOnly | Mode::Both)
+    }
+
+    /// Whether the mouse is moved
+    pub(crate) fn jiggles(self) -> bool {
+        matches!(self, Mode::JiggleOnly | Mode::Both)
+    }
+}
+
+/// What the policy decided to do until the next check
+#[derive(Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Decision {
+    /// Keep the system awake as configured by the mode
+    Active,
+    /// Only hold the power management lock, without moving the mouse
+    LockOnly,
+    /// Neither hold the lock nor move the mouse
+    Paused,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decision::Active => write!(f, "active"),
+            Decision::LockOnly => write!(f, "lock-only"),
+            Decision::Paused => write!(f, "paused"),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
 pub(crate) struct InitPoint {
     pub(crate) x: usize,
     pub(crate) y: usize,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub(crate) struct WorkingArea {
     pub(crate) width: usize,
     pub(crate) height: usize,
 }
 
+/// USB device identified by its vendor and product IDs
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub(crate) struct UsbId {
+    pub(crate) vendor: u16,
+    pub(crate) product: u16,
+}
+
+impl fmt::Display for UsbId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vendor, self.product)
+    }
+}
+
+impl FromStr for UsbId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse_err = |err: ParseIntError| anyhow!("parsing error: {err}");
+
+        match s.split_once(':') {
+            Some((vendor, product)) => Ok(UsbId {
+                vendor: u16::from_str_radix(vendor, 16).map_err(parse_err)?,
+                product: u16::from_str_radix(product, 16).map_err(parse_err)?,
+            }),
+            None => Err(anyhow!(r#"expected format: "17ef:a396""#)),
+        }
+    }
+}
+
 fn parse_points(s: &str) -> Result<(usize, usize)> {
     let split: Vec<&str> = s.split('x').take(3).collect(); // Taking 3 instead of 2, to test correctness of the parser
 
@@ -66,6 +141,18 @@ impl<'de> Deserialize<'de> for WorkingArea {
     }
 }
 
+impl<'de> Deserialize<'de> for UsbId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = Deserialize::deserialize(deserializer)?;
+
+        s.parse()
+            .map_err(|err| D::Error::custom(format!("[USB_DEVICE ERROR] {err}")))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::config::Config;
@@ -219,4 +306,44 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_usb_id_deserialization() {
+        let example_json = r#"{ "keep_awake_while_usb_device": ["17ef:a396", "046D:C52B"] }"#;
+        let test_config: Config = serde_json::from_str(example_json).unwrap();
+
+        assert_eq!(
+            test_config.keep_awake_while_usb_device,
+            [
+                UsbId {
+                    vendor: 0x17ef,
+                    product: 0xa396
+                },
+                UsbId {
+                    vendor: 0x046d,
+                    product: 0xc52b
+                }
+            ]
+        );
+        assert_eq!(
+            test_config.keep_awake_while_usb_device[1].to_string(),
+            "046d:c52b"
+        );
+
+        // ----------
+
+        let example_json = r#"{ "keep_awake_while_usb_device": ["17efa396"] }"#;
+        let err = serde_json::from_str::<Config>(example_json).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("[USB_DEVICE ERROR] expected format"));
+
+        // ----------
+
+        let example_json = r#"{ "keep_awake_while_usb_device": ["17ef:dock"] }"#;
+        let err = serde_json::from_str::<Config>(example_json).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("[USB_DEVICE ERROR] parsing error"));
+    }
 }
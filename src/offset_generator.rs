@@ -135,6 +135,7 @@ mod tests {
             jump_by_pixel_max,
             init_point,
             working_area,
+            ..Default::default()
         };
 
         test_config.validate()?;
@@ -0,0 +1,435 @@
+//! Expressions combining conditions, e.g. `(weekday AND hours(9-17) AND on-ac) OR process(rsync)`
+
+use std::{fmt, time::Instant};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::conditions::{Condition, Overrides};
+
+/// Creates the condition of an atom from its name and optional argument, e.g. `process` and `rsync`
+pub(crate) type Factory<'a> = dyn FnMut(&str, Option<&str>) -> Result<Box<dyn Condition>> + 'a;
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Atom {
+        name: &'a str,
+        argument: Option<&'a str>,
+        text: &'a str,
+    },
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let (token, len) = match c {
+            '(' => (Token::Open, 1),
+            ')' => (Token::Close, 1),
+            _ => {
+                let name_len = rest
+                    .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                    .unwrap_or(rest.len());
+                let name = &rest[..name_len];
+
+                match name.to_ascii_uppercase().as_str() {
+                    "AND" => (Token::And, name_len),
+                    "OR" => (Token::Or, name_len),
+                    "NOT" => (Token::Not, name_len),
+                    // The argument of an atom follows its name without any whitespace
+                    _ if rest[name_len..].starts_with('(') => {
+                        let close = name_len
+                            + closing_parenthesis(&rest[name_len..])
+                                .ok_or_else(|| anyhow!("missing `)` after `{name}(`"))?;
+                        let atom = Token::Atom {
+                            name,
+                            argument: Some(rest[name_len + 1..close].trim()),
+                            text: &rest[..=close],
+                        };
+                        (atom, close + 1)
+                    }
+                    _ => {
+                        let atom = Token::Atom {
+                            name,
+                            argument: None,
+                            text: name,
+                        };
+                        (atom, name_len)
+                    }
+                }
+            }
+        };
+
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Position of the `)` matching the `(` at the start of `s`
+fn closing_parenthesis(s: &str) -> Option<usize> {
+    let mut depth = 0;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Condition as written in the expression, with the result of its last check
+#[derive(Debug)]
+pub(crate) struct Atom {
+    text: String,
+    condition: Box<dyn Condition>,
+    value: bool,
+}
+
+#[derive(Debug)]
+pub(crate) enum Expr {
+    Atom(Atom),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl Expr {
+    /// Wraps a single condition
+    pub(crate) fn condition(text: &str, condition: Box<dyn Condition>) -> Self {
+        Expr::Atom(Atom {
+            text: text.to_owned(),
+            condition,
+            value: false,
+        })
+    }
+
+    pub(crate) fn parse(source: &str, factory: &mut Factory<'_>) -> Result<Self> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+            factory,
+        };
+
+        let expr = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(expr),
+            Some(Token::Close) => bail!("unexpected `)`"),
+            Some(_) => bail!("missing `AND` or `OR` between conditions"),
+        }
+    }
+
+    /// Checks every condition, none of them is skipped so that all can keep track of their state
+    pub(crate) fn check(&mut self) -> bool {
+        match self {
+            Expr::Atom(atom) => {
+                atom.value = atom.condition.check();
+                atom.value
+            }
+            Expr::Not(expr) => !expr.check(),
+            Expr::And(exprs) => exprs
+                .iter_mut()
+                .fold(true, |value, expr| expr.check() & value),
+            Expr::Or(exprs) => exprs
+                .iter_mut()
+                .fold(false, |value, expr| expr.check() | value),
+        }
+    }
+
+    /// Settings overridden by the conditions, the first condition overriding a setting wins
+    pub(crate) fn overrides(&self) -> Overrides {
+        match self {
+            Expr::Atom(atom) => atom.condition.overrides(),
+            Expr::Not(expr) => expr.overrides(),
+            Expr::And(exprs) | Expr::Or(exprs) => {
+                exprs.iter().fold(Overrides::default(), |overrides, expr| {
+                    overrides.or(expr.overrides())
+                })
+            }
+        }
+    }
+
+    /// While the expression doesn't hold, the earliest time it can hold again, `None` if it can at any time
+    pub(crate) fn holds_again_at(&self) -> Option<Instant> {
+        if self.value() {
+            return None;
+        }
+
+        match self {
+            Expr::Atom(atom) => atom.condition.holds_again_at(),
+            Expr::Not(_) => None,
+            // Waits for all the operands that don't hold
+            Expr::And(exprs) => exprs.iter().filter_map(Expr::holds_again_at).max(),
+            // Waits for the first operand, none of which holds
+            Expr::Or(exprs) => exprs
+                .iter()
+                .map(Expr::holds_again_at)
+                .collect::<Option<Vec<_>>>()?
+                .into_iter()
+                .min(),
+        }
+    }
+
+    /// Whether the result of any condition is not known yet
+    pub(crate) fn pending(&self) -> bool {
+        match self {
+            Expr::Atom(atom) => atom.condition.pending(),
+            Expr::Not(expr) => expr.pending(),
+            Expr::And(exprs) | Expr::Or(exprs) => exprs.iter().any(Expr::pending),
+        }
+    }
+
+    /// Result of the last check
+    pub(crate) fn value(&self) -> bool {
+        match self {
+            Expr::Atom(atom) => atom.value,
+            Expr::Not(expr) => !expr.value(),
+            Expr::And(exprs) => exprs.iter().all(Expr::value),
+            Expr::Or(exprs) => exprs.iter().any(Expr::value),
+        }
+    }
+
+    /// The expression annotated with the result of the last check of each condition
+    pub(crate) fn explain(&self) -> Explain<'_> {
+        Explain(self)
+    }
+}
+
+/// Displays an expression with `[yes]`, `[no]` or `[pending]` after each condition
+pub(crate) struct Explain<'a>(&'a Expr);
+
+impl fmt::Display for Explain<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nested = |f: &mut fmt::Formatter<'_>, expr: &Expr| match expr {
+            Expr::And(_) | Expr::Or(_) => write!(f, "({})", expr.explain()),
+            _ => write!(f, "{}", expr.explain()),
+        };
+
+        let join = |f: &mut fmt::Formatter<'_>, exprs: &[Expr], operator: &str| {
+            for (i, expr) in exprs.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " {operator} ")?;
+                }
+                nested(f, expr)?;
+            }
+            Ok(())
+        };
+
+        match self.0 {
+            Expr::Atom(atom) => {
+                let value = match (atom.condition.pending(), atom.value) {
+                    (true, _) => "pending",
+                    (false, true) => "yes",
+                    (false, false) => "no",
+                };
+                write!(f, "{} [{value}]", atom.text)
+            }
+            Expr::Not(expr) => {
+                write!(f, "NOT ")?;
+                nested(f, expr)
+            }
+            Expr::And(exprs) => join(f, exprs, "AND"),
+            Expr::Or(exprs) => join(f, exprs, "OR"),
+        }
+    }
+}
+
+/// Recursive descent parser, `NOT` binds tighter than `AND`, which binds tighter than `OR`
+struct Parser<'t, 's, 'f, 'a> {
+    tokens: &'t [Token<'s>],
+    position: usize,
+    factory: &'f mut Factory<'a>,
+}
+
+impl Parser<'_, '_, '_, '_> {
+    fn next_is(&self, token: &Token<'_>) -> bool {
+        self.tokens.get(self.position) == Some(token)
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut exprs = vec![self.and()?];
+        while self.next_is(&Token::Or) {
+            self.position += 1;
+            exprs.push(self.and()?);
+        }
+
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::Or(exprs)
+        })
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut exprs = vec![self.not()?];
+        while self.next_is(&Token::And) {
+            self.position += 1;
+            exprs.push(self.not()?);
+        }
+
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::And(exprs)
+        })
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.next_is(&Token::Not) {
+            self.position += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| anyhow!("unexpected end of the expression, a condition is missing"))?;
+        self.position += 1;
+
+        match token {
+            Token::Open => {
+                let expr = self.or()?;
+                if !self.next_is(&Token::Close) {
+                    bail!("missing `)`");
+                }
+                self.position += 1;
+                Ok(expr)
+            }
+            Token::Atom {
+                name,
+                argument,
+                text,
+            } => {
+                let condition = (self.factory)(name, *argument)
+                    .map_err(|err| anyhow!("invalid condition `{text}`: {err}"))?;
+                Ok(Expr::condition(text, condition))
+            }
+            Token::Close => bail!("unexpected `)`"),
+            Token::And | Token::Or => bail!("a condition is missing before `AND` or `OR`"),
+            Token::Not => unreachable!("handled by Parser::not"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Condition named after its value
+    #[derive(Debug)]
+    struct Fixed(bool);
+
+    impl Condition for Fixed {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        fn check(&mut self) -> bool {
+            self.0
+        }
+    }
+
+    fn parse(source: &str) -> Result<Expr> {
+        Expr::parse(source, &mut |name, argument| match (name, argument) {
+            ("yes", None) => Ok(Box::new(Fixed(true))),
+            ("no", None) => Ok(Box::new(Fixed(false))),
+            ("is", Some(argument)) => Ok(Box::new(Fixed(argument == "yes"))),
+            _ => bail!("unknown condition"),
+        })
+    }
+
+    fn check(source: &str) -> bool {
+        parse(source).unwrap().check()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("(weekday and hours( 9-17 )) OR NOT process(train(ing)?\\.py)").unwrap(),
+            [
+                Token::Open,
+                Token::Atom {
+                    name: "weekday",
+                    argument: None,
+                    text: "weekday"
+                },
+                Token::And,
+                Token::Atom {
+                    name: "hours",
+                    argument: Some("9-17"),
+                    text: "hours( 9-17 )"
+                },
+                Token::Close,
+                Token::Or,
+                Token::Not,
+                Token::Atom {
+                    name: "process",
+                    argument: Some("train(ing)?\\.py"),
+                    text: "process(train(ing)?\\.py)"
+                },
+            ]
+        );
+
+        assert!(tokenize("process(rsync").is_err());
+    }
+
+    #[test]
+    fn test_evaluation() {
+        assert!(check("yes"));
+        assert!(!check("no"));
+        assert!(check("NOT no"));
+        assert!(check("yes AND yes"));
+        assert!(!check("yes AND no"));
+        assert!(check("no OR yes"));
+        // AND binds tighter than OR
+        assert!(check("yes OR yes AND no"));
+        assert!(!check("(yes OR yes) AND no"));
+        assert!(check("not (no or no) and is(yes)"));
+        assert!(!check("is(no)"));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        for source in [
+            "", "yes AND", "OR yes", "yes no", "(yes", "yes)", "NOT", "maybe", "is",
+        ] {
+            assert!(parse(source).is_err(), "{source:?} should not parse");
+        }
+
+        let err = parse("yes AND maybe").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid condition `maybe`: unknown condition"
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let mut expr = parse("(yes AND is(no)) OR NOT no").unwrap();
+        assert!(expr.check());
+        assert!(expr.value());
+        assert_eq!(
+            expr.explain().to_string(),
+            "(yes [yes] AND is(no) [no]) OR NOT no [no]"
+        );
+    }
+}
@@ -0,0 +1,556 @@
+//! Policy deciding on every tick whether the system is kept awake, and how
+
+use std::{fmt::Write, fs, time::Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{
+    conditions::{
+        activity::{Activity, Thresholds},
+        calendar::Calendar,
+        file_exists::FileExists,
+        hardware::{ExternalMonitor, UsbDevice},
+        plugin::Plugin,
+        power_supply::PowerSupply,
+        process::ProcessRunning,
+        schedule::InSchedule,
+        tcp::TcpSessions,
+        time::{DayOfWeek, Hours},
+        Condition, Conditions, Overrides,
+    },
+    config::Config,
+    models::{Decision, UsbId},
+};
+
+use self::expr::Expr;
+
+mod expr;
+
+/// Provides default value for the decision made when no rule matches
+fn default_decision() -> Decision {
+    Decision::Paused
+}
+
+/// Provides default value for the decision of a rule
+fn default_rule_decision() -> Decision {
+    Decision::Active
+}
+
+/// Policy file (`POLICY_FILE`)
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default = "default_decision")]
+    default: Decision,
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleFile>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    name: Option<String>,
+    when: String,
+    #[serde(default = "default_rule_decision")]
+    then: Decision,
+}
+
+#[derive(Debug)]
+struct Rule {
+    name: String,
+    when: Expr,
+    then: Decision,
+}
+
+/// Ordered rules, the first one whose expression holds decides
+#[derive(Debug)]
+pub(crate) struct Policy {
+    rules: Vec<Rule>,
+    /// Decision made when no rule matches
+    default: Decision,
+    /// Rule matched by the previous check
+    matched: Option<usize>,
+}
+
+impl Policy {
+    pub(crate) fn from_config(config: &Config) -> Result<Self> {
+        let mut factory = |name: &str, argument: Option<&str>| condition(config, name, argument);
+
+        if let Some(source) = &config.policy {
+            let when = Expr::parse(source, &mut factory).context("Invalid POLICY")?;
+            return Ok(Self::new(
+                vec![Rule {
+                    name: "policy".to_owned(),
+                    when,
+                    then: Decision::Active,
+                }],
+                Decision::Paused,
+            ));
+        }
+
+        if let Some(path) = &config.policy_file {
+            let source = fs::read_to_string(path)
+                .with_context(|| format!("Cannot read policy file {}", path.display()))?;
+            return Self::from_toml(&source, &mut factory)
+                .with_context(|| format!("Invalid policy file {}", path.display()));
+        }
+
+        // Without a policy the system is kept awake while the configured conditions hold
+        let conditions = Conditions::from_config(config)?;
+        Ok(Self::new(
+            vec![Rule {
+                name: "conditions".to_owned(),
+                when: Expr::condition("conditions", Box::new(conditions)),
+                then: Decision::Active,
+            }],
+            Decision::Paused,
+        ))
+    }
+
+    fn new(rules: Vec<Rule>, default: Decision) -> Self {
+        Self {
+            rules,
+            default,
+            matched: None,
+        }
+    }
+
+    fn from_toml(source: &str, factory: &mut expr::Factory<'_>) -> Result<Self> {
+        let file: PolicyFile = toml::from_str(source)?;
+
+        let rules = file
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(i, rule)| {
+                let name = rule.name.unwrap_or_else(|| format!("#{}", i + 1));
+                let when = Expr::parse(&rule.when, factory)
+                    .with_context(|| format!("Invalid rule `{name}`"))?;
+                Ok(Rule {
+                    name,
+                    when,
+                    then: rule.then,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self::new(rules, file.default))
+    }
+
+    /// Checks all the rules and decides what to do until the next check
+    pub(crate) fn decide(&mut self) -> Decision {
+        for rule in &mut self.rules {
+            rule.when.check();
+        }
+
+        let matched = self.rules.iter().position(|rule| rule.when.value());
+
+        if matched != self.matched {
+            match matched {
+                Some(i) => {
+                    info!(rule = self.rules[i].name, decision = ?self.rules[i].then, "Policy rule matched")
+                }
+                None => info!(decision = ?self.default, "No policy rule matched"),
+            }
+            self.matched = matched;
+        }
+
+        matched.map_or(self.default, |i| self.rules[i].then)
+    }
+
+    /// Settings overridden by the conditions of the rule matched by the last check
+    pub(crate) fn overrides(&self) -> Overrides {
+        self.matched
+            .map_or_else(Overrides::default, |i| self.rules[i].when.overrides())
+    }
+
+    /// While no rule matches, the earliest time one of them can match, `None` if it can at any time
+    pub(crate) fn holds_again_at(&self) -> Option<Instant> {
+        if self.matched.is_some() {
+            return None;
+        }
+
+        self.rules
+            .iter()
+            .map(|rule| rule.when.holds_again_at())
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .min()
+    }
+
+    /// Whether the result of a condition of any rule is not known yet
+    pub(crate) fn pending(&self) -> bool {
+        self.rules.iter().any(|rule| rule.when.pending())
+    }
+
+    /// Describes the result of the last check of every rule
+    pub(crate) fn explain(&self) -> String {
+        let mut explanation = String::new();
+
+        for (i, rule) in self.rules.iter().enumerate() {
+            let result = match self.matched {
+                Some(matched) if matched == i => "matched",
+                Some(matched) if matched < i => "not needed",
+                _ => "not matched",
+            };
+
+            // Writing into a String cannot fail
+            let _ = writeln!(
+                explanation,
+                "Rule `{}` ({}): {} => {result}",
+                rule.name,
+                rule.then,
+                rule.when.explain()
+            );
+        }
+
+        let _ = match self.matched {
+            Some(i) => writeln!(
+                explanation,
+                "Decision: {} (rule `{}`)",
+                self.rules[i].then, self.rules[i].name
+            ),
+            None => writeln!(explanation, "Decision: {} (no rule matched)", self.default),
+        };
+
+        explanation
+    }
+}
+
+/// Creates the condition an expression refers to, e.g. `process(rsync)`
+fn condition(config: &Config, name: &str, argument: Option<&str>) -> Result<Box<dyn Condition>> {
+    let no_argument = || match argument {
+        None => Ok(()),
+        Some(_) => Err(anyhow!("`{name}` does not take an argument")),
+    };
+    let required = || argument.ok_or_else(|| anyhow!("`{name}` requires an argument"));
+    let activity = |thresholds| -> Result<Box<dyn Condition>> {
+        Ok(Box::new(Activity::new(
+            thresholds,
+            config.activity_quiet_period,
+        )?))
+    };
+
+    Ok(match name {
+        "conditions" => {
+            no_argument()?;
+            Box::new(Conditions::from_config(config)?)
+        }
+        "weekday" => {
+            no_argument()?;
+            Box::new(DayOfWeek::weekdays())
+        }
+        "weekend" => {
+            no_argument()?;
+            Box::new(DayOfWeek::weekend())
+        }
+        "hours" => Box::new(Hours::new(required()?.parse()?)),
+        "on-ac" => {
+            no_argument()?;
+            Box::new(PowerSupply::new(&config.sysfs_root, true, None)?)
+        }
+        "battery-above" => Box::new(PowerSupply::new(
+            &config.sysfs_root,
+            false,
+            Some(required()?.parse()?),
+        )?),
+        "process" => Box::new(ProcessRunning::new(&[required()?.to_owned()])?),
+        "tcp-sessions" => {
+            let ports = match argument {
+                Some(ports) => ports
+                    .split(',')
+                    .map(|port| port.trim().parse())
+                    .collect::<Result<Vec<u16>, _>>()?,
+                None => config.tcp_session_ports.clone(),
+            };
+            Box::new(TcpSessions::new(&ports)?)
+        }
+        "cpu-above" => activity(Thresholds {
+            cpu_percent: Some(required()?.parse()?),
+            ..Default::default()
+        })?,
+        "disk-above" => activity(Thresholds {
+            disk_kbps: Some(required()?.parse()?),
+            ..Default::default()
+        })?,
+        "net-above" => activity(Thresholds {
+            net_kbps: Some(required()?.parse()?),
+            ..Default::default()
+        })?,
+        "file-exists" => Box::new(FileExists::new(required()?.as_ref())),
+        #[cfg(target_os = "linux")]
+        "path-changes" => Box::new(crate::conditions::path_changes::PathChanges::new(
+            required()?.as_ref(),
+            config.path_changes_quiet_period,
+        )?),
+        // The module needs inotify, but the condition fails like the other Linux-only ones
+        #[cfg(not(target_os = "linux"))]
+        "path-changes" => bail!(
+            "KEEP_AWAKE_WHILE_PATH_CHANGES is only supported on Linux (cannot watch {})",
+            required()?
+        ),
+        "external-monitor" => {
+            no_argument()?;
+            Box::new(ExternalMonitor::new(&config.sysfs_root)?)
+        }
+        "usb" => Box::new(UsbDevice::new(
+            &config.sysfs_root,
+            &[required()?.parse::<UsbId>()?],
+        )?),
+        "schedule" => {
+            let schedule = match argument {
+                Some(schedule) => schedule.parse()?,
+                None => config.schedule.clone().ok_or_else(|| {
+                    anyhow!("`schedule` requires an argument unless SCHEDULE is set")
+                })?,
+            };
+            Box::new(InSchedule::new(schedule, config.schedule_timezone))
+        }
+        "calendar" => {
+            let path = match argument {
+                Some(path) => path.into(),
+                None => config.calendar_ics.clone().ok_or_else(|| {
+                    anyhow!("`calendar` requires an argument unless CALENDAR_ICS is set")
+                })?,
+            };
+            Box::new(Calendar::new(
+                &path,
+                config.calendar_filters(),
+                config.calendar_minutes_before,
+            )?)
+        }
+        "plugin" => Box::new(Plugin::new(
+            required()?,
+            config.plugin_interval,
+            config.plugin_timeout,
+        )),
+        _ => bail!("unknown condition"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Fixed(bool);
+
+    impl Condition for Fixed {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        fn check(&mut self) -> bool {
+            self.0
+        }
+    }
+
+    fn factory(name: &str, argument: Option<&str>) -> Result<Box<dyn Condition>> {
+        match (name, argument) {
+            ("yes", None) => Ok(Box::new(Fixed(true))),
+            ("no", None) => Ok(Box::new(Fixed(false))),
+            _ => bail!("unknown condition"),
+        }
+    }
+
+    const POLICY: &str = r#"
+        default = "paused"
+
+        [[rule]]
+        name = "office hours"
+        when = "(no AND yes) OR no"
+
+        [[rule]]
+        name = "ssh"
+        when = "NOT no"
+        then = "lock-only"
+
+        [[rule]]
+        when = "yes"
+        then = "active"
+    "#;
+
+    #[test]
+    fn test_first_matching_rule_decides() {
+        let mut policy = Policy::from_toml(POLICY, &mut factory).unwrap();
+        assert_eq!(policy.decide(), Decision::LockOnly);
+        assert_eq!(
+            policy.explain(),
+            "Rule `office hours` (active): (no [no] AND yes [yes]) OR no [no] => not matched
+Rule `ssh` (lock-only): NOT no [no] => matched
+Rule `#3` (active): yes [yes] => not needed
+Decision: lock-only (rule `ssh`)
+"
+        );
+
+        let mut policy = Policy::from_toml(
+            "default = \"lock-only\"\n[[rule]]\nwhen = \"no\"",
+            &mut factory,
+        )
+        .unwrap();
+        assert_eq!(policy.decide(), Decision::LockOnly);
+        assert!(policy
+            .explain()
+            .ends_with("Decision: lock-only (no rule matched)\n"));
+
+        let mut policy = Policy::from_toml("", &mut factory).unwrap();
+        assert_eq!(policy.decide(), Decision::Paused);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_plugin_overrides() {
+        let config = Config {
+            policy: Some(
+                r#"plugin(echo '{"interval": 5, "mode": "lock-only"}') OR plugin(echo '{"interval": 60}')"#
+                    .to_owned(),
+            ),
+            ..Default::default()
+        };
+        let mut policy = Policy::from_config(&config).unwrap();
+        assert_eq!(policy.overrides(), Overrides::default());
+
+        // Plugins run in the background, they don't hold before their first run finishes
+        let expected = Overrides {
+            interval: Some(Duration::from_secs(5)),
+            mode: Some(crate::models::Mode::LockOnly),
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        // The overrides come from the first plugin, which can finish after the second one
+        while policy.decide() != Decision::Active || policy.overrides() != expected {
+            assert!(Instant::now() < deadline, "plugins did not finish");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pending_plugin() {
+        let config = Config {
+            policy: Some("plugin(sleep 10) OR plugin(exit 0)".to_owned()),
+            plugin_timeout: Duration::from_secs(10),
+            ..Default::default()
+        };
+        let mut policy = Policy::from_config(&config).unwrap();
+
+        policy.decide();
+        assert!(policy.pending());
+        assert!(policy
+            .explain()
+            .starts_with("Rule `policy` (active): plugin(sleep 10) [pending] OR plugin(exit 0) ["));
+    }
+
+    #[test]
+    fn test_invalid_policy_file() {
+        for source in [
+            "[[rule]]\nwhen = \"yes AND\"",
+            "[[rule]]\nwhen = \"maybe\"",
+            "[[rule]]\nthen = \"active\"",
+            "[[rule]]\nwhen = \"yes\"\nthen = \"sleep\"",
+            "[[rules]]\nwhen = \"yes\"",
+        ] {
+            assert!(
+                Policy::from_toml(source, &mut factory).is_err(),
+                "{source:?} should not be valid"
+            );
+        }
+    }
+
+    #[test]
+    fn test_conditions() {
+        let config = Config::default();
+        let parse = |source: &str| {
+            Expr::parse(source, &mut |name: &str, argument: Option<&str>| {
+                condition(&config, name, argument)
+            })
+        };
+
+        assert!(parse("weekday OR weekend").unwrap().check());
+        assert!(
+            parse("hours(0-24) AND NOT hours(12-12:01) OR hours(12-12:01)")
+                .unwrap()
+                .check()
+        );
+        assert!(parse("conditions").unwrap().check());
+        assert!(!parse("file-exists(/stayawake/does/not/exist)")
+            .unwrap()
+            .check());
+        assert!(
+            parse("schedule(Mon-Sun 0-24) AND NOT schedule(Mon 0-24; Tue-Sun 0-24)")
+                .is_ok_and(|mut expr| !expr.check())
+        );
+
+        for source in [
+            "weekday(1)",
+            "hours",
+            "hours(9)",
+            "usb(dock)",
+            "schedule",
+            "schedule(Mon-Fri)",
+            "calendar",
+            "calendar(/stayawake/does/not/exist.ics)",
+            "battery-above(many)",
+            "rsync",
+        ] {
+            assert!(parse(source).is_err(), "{source:?} should not be valid");
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_linux_conditions() {
+        let config = Config::default();
+        let parse = |source: &str| {
+            Expr::parse(source, &mut |name: &str, argument: Option<&str>| {
+                condition(&config, name, argument)
+            })
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        assert!(parse(&format!(
+            "process(rsync) OR tcp-sessions OR tcp-sessions(22,2222) OR cpu-above(20) OR disk-above(100) OR net-above(500) OR path-changes({})",
+            dir.path().display()
+        ))
+        .is_ok());
+        assert!(parse("usb(17ef:a396) OR external-monitor OR on-ac OR battery-above(20)").is_ok());
+        let mut plugins =
+            parse("plugin(exit 0) AND NOT plugin(test -e /stayawake/does/not/exist)").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !plugins.check() {
+            assert!(Instant::now() < deadline, "plugins did not finish");
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(parse("process((rsync)").is_err());
+        assert!(parse("tcp-sessions(ssh)").is_err());
+        assert!(parse("path-changes(/stayawake/does/not/exist)").is_err());
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[test]
+    fn test_linux_only_conditions() {
+        let config = Config::default();
+
+        for source in [
+            "process(rsync)",
+            "tcp-sessions",
+            "cpu-above(20)",
+            "usb(17ef:a396)",
+            "external-monitor",
+            "on-ac",
+            "battery-above(20)",
+            "path-changes(/tmp)",
+        ] {
+            let parsed = Expr::parse(source, &mut |name: &str, argument: Option<&str>| {
+                condition(&config, name, argument)
+            });
+            assert!(parsed.is_err(), "{source:?} should only be valid on Linux");
+        }
+    }
+}
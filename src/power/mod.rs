@@ -1,17 +1,232 @@
-#[cfg(any(target_os = "windows", target_os = "macos"))]
-const POWER_DEFAULT_MESSAGE: &str = "StayAwake program is running";
+use serde::Deserialize;
+use std::{collections::BTreeSet, fmt::Debug};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+pub const POWER_DEFAULT_MESSAGE: &str = "StayAwake program is running";
+
+pub const POWER_DEFAULT_WHO: &str = "StayAwake";
 
 /// Platform-specific types
 pub mod platform;
 
-pub trait Lock: Send {
-    type Error: std::error::Error;
-    type Lock: Lock;
+type BoxedError = Box<dyn std::error::Error + Send + Sync>;
+
+/// What the operating system is kept from doing while a [`Lock`] is held
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum InhibitKind {
+    /// Suspending or hibernating the system
+    SystemSleep,
+    /// Turning off or blanking the display
+    DisplaySleep,
+    /// Marking the session as idle (screensaver, automatic idle actions)
+    Idle,
+}
+
+/// All kinds of inhibition
+pub const ALL_INHIBIT_KINDS: &[InhibitKind] = &[
+    InhibitKind::SystemSleep,
+    InhibitKind::DisplaySleep,
+    InhibitKind::Idle,
+];
+
+/// Set of inhibition kinds
+pub type InhibitKinds = BTreeSet<InhibitKind>;
+
+/// What to inhibit and how the lock is presented to the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockRequest {
+    pub kinds: InhibitKinds,
+    /// Name of the application holding the lock
+    pub who: String,
+    /// Why the system is kept awake
+    pub reason: String,
+}
+
+/// Power management lock, the system is kept awake until it is dropped
+pub trait Lock: Send + Debug {}
+
+/// Creates a [`Lock`] of a specific backend
+pub trait Acquire: Lock + Sized + 'static {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Kinds of inhibition the backend is able to provide
+    const SUPPORTED: &'static [InhibitKind];
+
+    /// Acquires a lock, the requested kinds are never empty and always supported
+    fn acquire(request: &LockRequest) -> Result<Self, Self::Error>;
+}
+
+/// Named power management backend
+pub struct Backend {
+    pub name: &'static str,
+    supported: &'static [InhibitKind],
+    acquire: fn(&LockRequest) -> Result<Box<dyn Lock>, BoxedError>,
+}
+
+impl Backend {
+    const fn new<L: Acquire>(name: &'static str) -> Self {
+        Self {
+            name,
+            supported: L::SUPPORTED,
+            acquire: acquire_boxed::<L>,
+        }
+    }
+}
+
+fn acquire_boxed<L: Acquire>(request: &LockRequest) -> Result<Box<dyn Lock>, BoxedError> {
+    Ok(Box::new(L::acquire(request)?))
+}
+
+/// Locks of several backends, which together cover the requested kinds
+#[derive(Debug)]
+struct Locks {
+    _locks: Vec<Box<dyn Lock>>,
+}
+
+impl Lock for Locks {}
 
-    fn new() -> Result<Self::Lock, Self::Error>;
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("unknown power backend `{0}`")]
+    UnknownBackend(String),
+    #[error("no power backend available ({0})")]
+    Unavailable(String),
 }
 
-/// Constructs a new [`Lock`] for the current platform.
-pub fn lock() -> Result<platform::Lock, <platform::Lock as Lock>::Error> {
-    platform::Lock::new()
+/// Names of the backends available on the current platform, in their default order
+pub fn backend_names() -> impl Iterator<Item = &'static str> {
+    platform::BACKENDS.iter().map(|backend| backend.name)
+}
+
+/// Constructs a new [`Lock`] for the given request.
+///
+/// Backends are tried in the given `order`, or in the platform's default order if it is empty.
+/// Once a backend succeeds, the remaining backends are only used for kinds it cannot provide.
+pub fn lock(order: &[String], request: &LockRequest) -> Result<Box<dyn Lock>, Error> {
+    let backends = if order.is_empty() {
+        platform::BACKENDS.iter().collect()
+    } else {
+        order
+            .iter()
+            .map(|name| {
+                platform::BACKENDS
+                    .iter()
+                    .find(|backend| backend.name == name)
+                    .ok_or_else(|| Error::UnknownBackend(name.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    let mut remaining = request.kinds.clone();
+    let mut locks = Vec::new();
+    let mut failures = Vec::new();
+
+    for backend in backends {
+        if remaining.is_empty() {
+            break;
+        }
+
+        let (requested, unsupported): (InhibitKinds, InhibitKinds) = remaining
+            .iter()
+            .partition(|kind| backend.supported.contains(kind));
+
+        if requested.is_empty() {
+            debug!(
+                backend = backend.name,
+                ?unsupported,
+                "Power backend skipped"
+            );
+            failures.push(format!("{}: cannot inhibit {unsupported:?}", backend.name));
+            continue;
+        }
+
+        let backend_request = LockRequest {
+            kinds: requested,
+            ..request.clone()
+        };
+
+        match (backend.acquire)(&backend_request) {
+            Ok(lock) => {
+                info!(
+                    backend = backend.name,
+                    kinds = ?backend_request.kinds,
+                    ?unsupported,
+                    "Power Management inhibited"
+                );
+                locks.push(lock);
+                remaining = unsupported;
+            }
+            Err(err) => {
+                info!(backend = backend.name, error = %err, "Power backend unavailable");
+                failures.push(format!("{}: {err}", backend.name));
+            }
+        }
+    }
+
+    if locks.is_empty() {
+        return Err(Error::Unavailable(failures.join("; ")));
+    }
+
+    if !remaining.is_empty() {
+        warn!(kinds = ?remaining, "No power backend can inhibit some of the requested kinds");
+    }
+
+    Ok(Box::new(Locks { _locks: locks }))
+}
+
+/// Power management lock as configured, which can be acquired and released at any time
+#[derive(Debug)]
+pub struct Inhibitor {
+    backends: Vec<String>,
+    request: LockRequest,
+    lock: Option<Box<dyn Lock>>,
+}
+
+impl Inhibitor {
+    pub fn new(backends: Vec<String>, request: LockRequest) -> Self {
+        Self {
+            backends,
+            request,
+            lock: None,
+        }
+    }
+
+    /// Acquires the lock, unless it is already held
+    pub fn acquire(&mut self) -> Result<(), Error> {
+        if self.lock.is_none() {
+            self.lock = Some(lock(&self.backends, &self.request)?);
+        }
+
+        Ok(())
+    }
+
+    /// Releases the lock, if it is held
+    pub fn release(&mut self) {
+        self.lock = None;
+    }
+
+    /// Changes the application name and reason shown by the operating system.
+    ///
+    /// A held lock is acquired again with the new values, the old one is only released afterwards. If that fails, the
+    /// old lock and values are kept.
+    pub fn set_reason(&mut self, who: String, reason: String) -> Result<(), Error> {
+        if self.request.who == who && self.request.reason == reason {
+            return Ok(());
+        }
+
+        let request = LockRequest {
+            who,
+            reason,
+            ..self.request.clone()
+        };
+
+        if self.lock.is_some() {
+            self.lock = Some(lock(&self.backends, &request)?);
+        }
+
+        self.request = request;
+        Ok(())
+    }
 }
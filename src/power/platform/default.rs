@@ -1,3 +1,8 @@
+use crate::power::{Backend, InhibitKind, LockRequest};
+
+/// Unsupported platforms only have a backend that always fails
+pub const BACKENDS: &[Backend] = &[Backend::new::<Lock>("unsupported")];
+
 #[derive(Debug)]
 pub enum Error {
     UnsupportedOperatingSystem,
@@ -20,11 +25,14 @@ impl Lock {
     }
 }
 
-impl crate::power::Lock for Lock {
+impl crate::power::Lock for Lock {}
+
+impl crate::power::Acquire for Lock {
     type Error = Error;
-    type Lock = Lock;
 
-    fn new() -> Result<Lock, Self::Error> {
+    const SUPPORTED: &'static [InhibitKind] = crate::power::ALL_INHIBIT_KINDS;
+
+    fn acquire(_request: &LockRequest) -> Result<Self, Self::Error> {
         Lock::new()
     }
 }
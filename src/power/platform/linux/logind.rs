@@ -0,0 +1,182 @@
+use zbus::blocking::Connection;
+use zbus::dbus_proxy;
+use zbus::zvariant::OwnedFd;
+
+use super::Error;
+use crate::power::{InhibitKind, InhibitKinds, LockRequest};
+
+/// Block the operations instead of only delaying them
+const INHIBIT_MODE: &str = "block";
+
+#[dbus_proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::Result<OwnedFd>;
+}
+
+#[derive(Debug)]
+pub struct Lock {
+    /// The inhibitor is held for as long as this descriptor stays open (it is closed on drop)
+    _fd: OwnedFd,
+}
+
+impl Lock {
+    fn new(request: &LockRequest) -> Result<Self, Error> {
+        let connection = Connection::system().map_err(Error::FailedToConnect)?;
+        Self::with_connection(&connection, request)
+    }
+
+    fn with_connection(connection: &Connection, request: &LockRequest) -> Result<Self, Error> {
+        let manager = ManagerProxyBlocking::new(connection).map_err(Error::FailedToLock)?;
+        let fd = manager
+            .inhibit(
+                &Self::what(&request.kinds),
+                &request.who,
+                &request.reason,
+                INHIBIT_MODE,
+            )
+            .map_err(Error::FailedToLock)?;
+
+        Ok(Self { _fd: fd })
+    }
+
+    /// Colon-separated list of operations to inhibit
+    fn what(kinds: &InhibitKinds) -> String {
+        kinds
+            .iter()
+            .filter_map(|kind| match kind {
+                InhibitKind::SystemSleep => Some("sleep"),
+                InhibitKind::Idle => Some("idle"),
+                InhibitKind::DisplaySleep => None,
+            })
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+}
+
+impl crate::power::Lock for Lock {}
+
+impl crate::power::Acquire for Lock {
+    type Error = Error;
+
+    const SUPPORTED: &'static [InhibitKind] = &[InhibitKind::SystemSleep, InhibitKind::Idle];
+
+    fn acquire(request: &LockRequest) -> Result<Self, Self::Error> {
+        Lock::new(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{ErrorKind, Read},
+        os::unix::{
+            io::{FromRawFd, IntoRawFd},
+            net::UnixStream,
+        },
+        sync::{Arc, Mutex},
+    };
+
+    use zbus::{dbus_interface, fdo};
+
+    use super::*;
+    use crate::test_bus::TestBus;
+
+    /// Stub of `org.freedesktop.login1.Manager`, hands out one end of a socket pair per inhibitor
+    #[derive(Default)]
+    struct StubManager {
+        calls: Arc<Mutex<Vec<[String; 4]>>>,
+        peers: Arc<Mutex<Vec<UnixStream>>>,
+    }
+
+    #[dbus_interface(name = "org.freedesktop.login1.Manager")]
+    impl StubManager {
+        fn inhibit(
+            &self,
+            what: String,
+            who: String,
+            why: String,
+            mode: String,
+        ) -> fdo::Result<OwnedFd> {
+            let (ours, theirs) =
+                UnixStream::pair().map_err(|err| fdo::Error::IOError(err.to_string()))?;
+
+            self.calls.lock().unwrap().push([what, who, why, mode]);
+            self.peers.lock().unwrap().push(ours);
+
+            Ok(unsafe { OwnedFd::from_raw_fd(theirs.into_raw_fd()) })
+        }
+    }
+
+    #[test]
+    fn test_lock_holds_inhibitor_until_dropped() {
+        let bus = TestBus::start();
+
+        let stub = StubManager::default();
+        let calls = stub.calls.clone();
+        let peers = stub.peers.clone();
+
+        let _service = bus
+            .builder()
+            .name("org.freedesktop.login1")
+            .unwrap()
+            .serve_at("/org/freedesktop/login1", stub)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let request = LockRequest {
+            kinds: InhibitKinds::from([InhibitKind::SystemSleep, InhibitKind::Idle]),
+            who: "Tests".to_owned(),
+            reason: "Testing the logind backend".to_owned(),
+        };
+        let lock = Lock::with_connection(&bus.connect(), &request).unwrap();
+
+        assert_eq!(
+            calls.lock().unwrap().as_slice(),
+            &[[
+                "sleep:idle".to_owned(),
+                "Tests".to_owned(),
+                "Testing the logind backend".to_owned(),
+                "block".to_owned(),
+            ]]
+        );
+
+        let mut peer = peers.lock().unwrap().pop().unwrap();
+
+        // The inhibitor is still held, nothing to read yet
+        peer.set_nonblocking(true).unwrap();
+        let err = peer.read(&mut [0]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        // Dropping the lock closes the last copy of the descriptor
+        drop(lock);
+        peer.set_nonblocking(false).unwrap();
+        assert_eq!(peer.read(&mut [0]).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_lock_fails_without_logind() {
+        let bus = TestBus::start();
+
+        let request = LockRequest {
+            kinds: InhibitKinds::from([InhibitKind::Idle]),
+            who: "Tests".to_owned(),
+            reason: "Testing the logind backend".to_owned(),
+        };
+        let result = Lock::with_connection(&bus.connect(), &request);
+        assert!(matches!(result, Err(Error::FailedToLock(_))));
+    }
+
+    #[test]
+    fn test_what() {
+        let kinds = InhibitKinds::from([InhibitKind::Idle, InhibitKind::SystemSleep]);
+        assert_eq!(Lock::what(&kinds), "sleep:idle");
+
+        let kinds = InhibitKinds::from([InhibitKind::Idle, InhibitKind::DisplaySleep]);
+        assert_eq!(Lock::what(&kinds), "idle");
+    }
+}
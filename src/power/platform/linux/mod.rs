@@ -0,0 +1,28 @@
+use thiserror::Error;
+
+use crate::power::Backend;
+
+/// systemd-logind inhibitor locks
+pub mod logind;
+
+/// `org.freedesktop.portal.Inhibit` inhibition (Flatpak, Wayland)
+pub mod portal;
+
+/// `org.freedesktop.ScreenSaver` inhibition
+pub mod screensaver;
+
+/// Backends available on Linux, in their default order
+pub const BACKENDS: &[Backend] = &[
+    Backend::new::<logind::Lock>("logind"),
+    Backend::new::<portal::Lock>("portal"),
+    Backend::new::<screensaver::Lock>("screensaver"),
+];
+
+/// Failure of one of the D-Bus backends, the name of the backend is added by the caller
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("failed to connect to the bus: {0}")]
+    FailedToConnect(zbus::Error),
+    #[error("failed to inhibit: {0}")]
+    FailedToLock(zbus::Error),
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+use zbus::blocking::Connection;
+use zbus::dbus_proxy;
+use zbus::zvariant::{OwnedObjectPath, Value};
+
+use super::Error;
+use crate::power::{InhibitKind, InhibitKinds, LockRequest};
+
+/// Inhibit suspending the session
+const INHIBIT_SUSPEND: u32 = 4;
+/// Inhibit the session going idle
+const INHIBIT_IDLE: u32 = 8;
+
+#[dbus_proxy(
+    interface = "org.freedesktop.portal.Inhibit",
+    default_service = "org.freedesktop.portal.Desktop",
+    default_path = "/org/freedesktop/portal/desktop"
+)]
+trait Inhibit {
+    fn inhibit(
+        &self,
+        window: &str,
+        flags: u32,
+        options: HashMap<&str, Value<'_>>,
+    ) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.portal.Request",
+    default_service = "org.freedesktop.portal.Desktop"
+)]
+trait Request {
+    fn close(&self) -> zbus::Result<()>;
+}
+
+#[derive(Debug)]
+pub struct Lock {
+    /// Request object representing the inhibition, closing it ends the inhibition
+    request: RequestProxyBlocking<'static>,
+}
+
+impl Lock {
+    fn new(request: &LockRequest) -> Result<Self, Error> {
+        let connection = Connection::session().map_err(Error::FailedToConnect)?;
+        Self::with_connection(&connection, request)
+    }
+
+    fn with_connection(connection: &Connection, lock_request: &LockRequest) -> Result<Self, Error> {
+        let inhibit = InhibitProxyBlocking::new(connection).map_err(Error::FailedToLock)?;
+        // The portal identifies the application on its own, only the reason can be passed
+        let options = HashMap::from([("reason", Value::from(lock_request.reason.as_str()))]);
+        let handle = inhibit
+            .inhibit("", Self::flags(&lock_request.kinds), options)
+            .map_err(Error::FailedToLock)?;
+
+        let request = RequestProxyBlocking::builder(connection)
+            .path(handle)
+            .and_then(|builder| builder.build())
+            .map_err(Error::FailedToLock)?;
+
+        Ok(Self { request })
+    }
+
+    fn flags(kinds: &InhibitKinds) -> u32 {
+        kinds.iter().fold(0, |flags, kind| match kind {
+            InhibitKind::SystemSleep => flags | INHIBIT_SUSPEND,
+            // The display is turned off once the session goes idle
+            InhibitKind::DisplaySleep | InhibitKind::Idle => flags | INHIBIT_IDLE,
+        })
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _res = self.request.close();
+    }
+}
+
+impl crate::power::Lock for Lock {}
+
+impl crate::power::Acquire for Lock {
+    type Error = Error;
+
+    const SUPPORTED: &'static [InhibitKind] = crate::power::ALL_INHIBIT_KINDS;
+
+    fn acquire(request: &LockRequest) -> Result<Self, Self::Error> {
+        Lock::new(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use zbus::{dbus_interface, zvariant::OwnedValue};
+
+    use super::*;
+    use crate::test_bus::TestBus;
+
+    const REQUEST_PATH: &str = "/org/freedesktop/portal/desktop/request/1_1/stayawake";
+
+    #[derive(Debug, PartialEq)]
+    enum Call {
+        Inhibit(u32, String),
+        Close,
+    }
+
+    /// Stub of `org.freedesktop.portal.Inhibit`, always hands out the same request object
+    #[derive(Default)]
+    struct StubInhibit {
+        calls: Arc<Mutex<Vec<Call>>>,
+    }
+
+    #[dbus_interface(name = "org.freedesktop.portal.Inhibit")]
+    impl StubInhibit {
+        fn inhibit(
+            &self,
+            _window: String,
+            flags: u32,
+            options: HashMap<String, OwnedValue>,
+        ) -> OwnedObjectPath {
+            let reason = options
+                .get("reason")
+                .and_then(|reason| String::try_from(reason.clone()).ok())
+                .unwrap_or_default();
+
+            self.calls
+                .lock()
+                .unwrap()
+                .push(Call::Inhibit(flags, reason));
+            OwnedObjectPath::try_from(REQUEST_PATH).unwrap()
+        }
+    }
+
+    /// Stub of `org.freedesktop.portal.Request`
+    struct StubRequest {
+        calls: Arc<Mutex<Vec<Call>>>,
+    }
+
+    #[dbus_interface(name = "org.freedesktop.portal.Request")]
+    impl StubRequest {
+        fn close(&self) {
+            self.calls.lock().unwrap().push(Call::Close);
+        }
+    }
+
+    #[test]
+    fn test_lock_closes_request_on_drop() {
+        let bus = TestBus::start();
+
+        let stub = StubInhibit::default();
+        let calls = stub.calls.clone();
+        let request = StubRequest {
+            calls: calls.clone(),
+        };
+
+        let _service = bus
+            .builder()
+            .name("org.freedesktop.portal.Desktop")
+            .unwrap()
+            .serve_at("/org/freedesktop/portal/desktop", stub)
+            .unwrap()
+            .serve_at(REQUEST_PATH, request)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let request = LockRequest {
+            kinds: crate::power::ALL_INHIBIT_KINDS.iter().copied().collect(),
+            who: "Tests".to_owned(),
+            reason: "Testing the portal backend".to_owned(),
+        };
+        let lock = Lock::with_connection(&bus.connect(), &request).unwrap();
+        assert_eq!(lock.request.path().as_str(), REQUEST_PATH);
+        assert_eq!(
+            calls.lock().unwrap().as_slice(),
+            &[Call::Inhibit(12, "Testing the portal backend".to_owned())]
+        );
+
+        drop(lock);
+        assert_eq!(calls.lock().unwrap().last(), Some(&Call::Close));
+    }
+
+    #[test]
+    fn test_lock_fails_without_portal() {
+        let bus = TestBus::start();
+
+        let request = LockRequest {
+            kinds: InhibitKinds::from([InhibitKind::SystemSleep]),
+            who: "Tests".to_owned(),
+            reason: "Testing the portal backend".to_owned(),
+        };
+        let result = Lock::with_connection(&bus.connect(), &request);
+        assert!(matches!(result, Err(Error::FailedToLock(_))));
+    }
+
+    #[test]
+    fn test_flags() {
+        let kinds = InhibitKinds::from([InhibitKind::SystemSleep]);
+        assert_eq!(Lock::flags(&kinds), INHIBIT_SUSPEND);
+
+        let kinds = InhibitKinds::from([InhibitKind::DisplaySleep, InhibitKind::Idle]);
+        assert_eq!(Lock::flags(&kinds), INHIBIT_IDLE);
+    }
+}
@@ -0,0 +1,142 @@
+use zbus::blocking::Connection;
+use zbus::dbus_proxy;
+
+use super::Error;
+use crate::power::{InhibitKind, LockRequest};
+
+#[dbus_proxy(
+    interface = "org.freedesktop.ScreenSaver",
+    default_service = "org.freedesktop.ScreenSaver",
+    default_path = "/org/freedesktop/ScreenSaver"
+)]
+trait ScreenSaver {
+    fn inhibit(&self, application_name: &str, reason_for_inhibit: &str) -> zbus::Result<u32>;
+
+    fn un_inhibit(&self, cookie: u32) -> zbus::Result<()>;
+}
+
+#[derive(Debug)]
+pub struct Lock {
+    /// The inhibition is bound to this proxy's connection, so it has to stay open
+    screensaver: ScreenSaverProxyBlocking<'static>,
+    cookie: u32,
+}
+
+impl Lock {
+    fn new(request: &LockRequest) -> Result<Self, Error> {
+        let connection = Connection::session().map_err(Error::FailedToConnect)?;
+        Self::with_connection(&connection, request)
+    }
+
+    fn with_connection(connection: &Connection, request: &LockRequest) -> Result<Self, Error> {
+        let screensaver = ScreenSaverProxyBlocking::new(connection).map_err(Error::FailedToLock)?;
+        let cookie = screensaver
+            .inhibit(&request.who, &request.reason)
+            .map_err(Error::FailedToLock)?;
+
+        Ok(Self {
+            screensaver,
+            cookie,
+        })
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _res = self.screensaver.un_inhibit(self.cookie);
+    }
+}
+
+impl crate::power::Lock for Lock {}
+
+impl crate::power::Acquire for Lock {
+    type Error = Error;
+
+    /// Inhibiting the screensaver keeps both the display on and the session active
+    const SUPPORTED: &'static [InhibitKind] = &[InhibitKind::DisplaySleep, InhibitKind::Idle];
+
+    fn acquire(request: &LockRequest) -> Result<Self, Self::Error> {
+        Lock::new(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use zbus::dbus_interface;
+
+    use super::*;
+    use crate::test_bus::TestBus;
+
+    #[derive(Debug, PartialEq)]
+    enum Call {
+        Inhibit(String, String),
+        UnInhibit(u32),
+    }
+
+    /// Stub of `org.freedesktop.ScreenSaver`, records every call it receives
+    #[derive(Default)]
+    struct StubScreenSaver {
+        calls: Arc<Mutex<Vec<Call>>>,
+    }
+
+    #[dbus_interface(name = "org.freedesktop.ScreenSaver")]
+    impl StubScreenSaver {
+        fn inhibit(&self, application_name: String, reason_for_inhibit: String) -> u32 {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push(Call::Inhibit(application_name, reason_for_inhibit));
+            calls.len() as u32 + 41
+        }
+
+        fn un_inhibit(&self, cookie: u32) {
+            self.calls.lock().unwrap().push(Call::UnInhibit(cookie));
+        }
+    }
+
+    fn test_request() -> LockRequest {
+        LockRequest {
+            kinds: [InhibitKind::DisplaySleep].into(),
+            who: "Tests".to_owned(),
+            reason: "Testing the screensaver backend".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_lock_uninhibits_on_drop() {
+        let bus = TestBus::start();
+
+        let stub = StubScreenSaver::default();
+        let calls = stub.calls.clone();
+
+        let _service = bus
+            .builder()
+            .name("org.freedesktop.ScreenSaver")
+            .unwrap()
+            .serve_at("/org/freedesktop/ScreenSaver", stub)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let lock = Lock::with_connection(&bus.connect(), &test_request()).unwrap();
+        assert_eq!(lock.cookie, 42);
+        assert_eq!(
+            calls.lock().unwrap().as_slice(),
+            &[Call::Inhibit(
+                "Tests".to_owned(),
+                "Testing the screensaver backend".to_owned()
+            )]
+        );
+
+        drop(lock);
+        assert_eq!(calls.lock().unwrap().last(), Some(&Call::UnInhibit(42)));
+    }
+
+    #[test]
+    fn test_lock_fails_without_screensaver() {
+        let bus = TestBus::start();
+
+        let result = Lock::with_connection(&bus.connect(), &test_request());
+        assert!(matches!(result, Err(Error::FailedToLock(_))));
+    }
+}
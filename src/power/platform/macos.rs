@@ -1,13 +1,23 @@
-use crate::power::POWER_DEFAULT_MESSAGE;
+use crate::power::{Backend, InhibitKind, InhibitKinds, LockRequest};
 use core_foundation::base::TCFType;
 use core_foundation::string::{CFString, CFStringRef};
 use mach::kern_return::kern_return_t;
+use std::collections::BTreeSet;
 
 type IOReturn = kern_return_t;
 type IOPMAssertionLevel = u32;
 type IOPMAssertionId = u32;
 
 const K_IOPMASSERTION_LEVEL_ON: IOPMAssertionLevel = 255;
+const K_IORETURN_SUCCESS: IOReturn = 0;
+
+/// Prevents the system from sleeping automatically due to a lack of user activity
+const K_IOPMASSERTION_TYPE_PREVENT_USER_IDLE_SYSTEM_SLEEP: &str = "PreventUserIdleSystemSleep";
+/// Prevents the display from dimming automatically
+const K_IOPMASSERTION_TYPE_NO_DISPLAY_SLEEP: &str = "NoDisplaySleepAssertion";
+
+/// Backends available on macOS
+pub const BACKENDS: &[Backend] = &[Backend::new::<Lock>("iokit")];
 
 extern "C" {
     pub fn IOPMAssertionCreateWithName(
@@ -21,54 +31,88 @@ extern "C" {
 }
 
 #[derive(Debug)]
-pub enum Error {}
+pub enum Error {
+    FailedToCreateAssertion(IOReturn),
+}
 
 impl std::error::Error for Error {}
 
 impl std::fmt::Display for Error {
-    fn fmt(&self, _f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        unimplemented!()
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::FailedToCreateAssertion(_) => write!(f, "failed to create power assertion"),
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct Lock {
-    request: IOPMAssertionId,
+    requests: Vec<IOPMAssertionId>,
 }
 
 impl Lock {
-    pub fn new() -> Result<Self, Error> {
-        let k_iopmassertion_type_prevent_system_sleep: CFString =
-            CFString::from_static_string("NoDisplaySleepAssertion");
-        let mut id: IOPMAssertionId = 0;
-        let reason_cf = CFString::new(POWER_DEFAULT_MESSAGE);
-        unsafe {
-            let _ret = IOPMAssertionCreateWithName(
-                k_iopmassertion_type_prevent_system_sleep.as_concrete_TypeRef(),
-                K_IOPMASSERTION_LEVEL_ON,
-                reason_cf.as_concrete_TypeRef(),
-                &mut id,
-            );
+    pub fn new(request: &LockRequest) -> Result<Self, Error> {
+        let reason_cf = CFString::new(&request.reason);
+        let mut lock = Lock {
+            requests: Vec::new(),
+        };
+
+        for assertion_type in Self::assertion_types(&request.kinds) {
+            let assertion_type_cf = CFString::from_static_string(assertion_type);
+            let mut id: IOPMAssertionId = 0;
+            let ret = unsafe {
+                IOPMAssertionCreateWithName(
+                    assertion_type_cf.as_concrete_TypeRef(),
+                    K_IOPMASSERTION_LEVEL_ON,
+                    reason_cf.as_concrete_TypeRef(),
+                    &mut id,
+                )
+            };
+
+            if ret != K_IORETURN_SUCCESS {
+                // Assertions created so far are released when `lock` is dropped
+                return Err(Error::FailedToCreateAssertion(ret));
+            }
+
+            lock.requests.push(id);
         }
-        Ok(Lock { request: id })
+
+        Ok(lock)
+    }
+
+    fn assertion_types(kinds: &InhibitKinds) -> BTreeSet<&'static str> {
+        kinds
+            .iter()
+            .map(|kind| match kind {
+                InhibitKind::SystemSleep | InhibitKind::Idle => {
+                    K_IOPMASSERTION_TYPE_PREVENT_USER_IDLE_SYSTEM_SLEEP
+                }
+                InhibitKind::DisplaySleep => K_IOPMASSERTION_TYPE_NO_DISPLAY_SLEEP,
+            })
+            .collect()
     }
 }
 
 impl Drop for Lock {
     fn drop(&mut self) {
-        unsafe {
-            let _res = IOPMAssertionRelease(self.request);
+        for request in &self.requests {
+            unsafe {
+                let _res = IOPMAssertionRelease(*request);
+            }
         }
     }
 }
 
 unsafe impl Send for Lock {}
 
-impl crate::power::Lock for Lock {
+impl crate::power::Lock for Lock {}
+
+impl crate::power::Acquire for Lock {
     type Error = Error;
-    type Lock = Lock;
 
-    fn new() -> Result<Lock, Self::Error> {
-        Lock::new()
+    const SUPPORTED: &'static [InhibitKind] = crate::power::ALL_INHIBIT_KINDS;
+
+    fn acquire(request: &LockRequest) -> Result<Self, Self::Error> {
+        Lock::new(request)
     }
 }
@@ -1,17 +1,14 @@
-use wasmer_enumset::EnumSetType;
+use wasmer_enumset::{EnumSet, EnumSetType};
 use winapi::shared::minwindef::DWORD;
 use winapi::um::errhandlingapi;
 use winapi::um::minwinbase::REASON_CONTEXT;
 use winapi::um::winnt::{HANDLE, POWER_REQUEST_TYPE};
 use winapi::um::{handleapi, winbase, winnt};
 
-use crate::power::POWER_DEFAULT_MESSAGE;
+use crate::power::{Backend, InhibitKind, InhibitKinds, LockRequest};
 
-const REQUEST_TYPES: &[LockTypeSuspend] = &[
-    LockTypeSuspend::Automatic,
-    LockTypeSuspend::Manual,
-    LockTypeSuspend::Display,
-];
+/// Backends available on Windows
+pub const BACKENDS: &[Backend] = &[Backend::new::<Lock>("power-request")];
 
 #[derive(Debug)]
 pub enum Error {
@@ -43,37 +40,53 @@ impl std::fmt::Display for Error {
 #[derive(Debug)]
 pub struct Lock {
     request: PowerRequest,
+    request_types: EnumSet<LockTypeSuspend>,
 }
 
 impl Lock {
-    fn new() -> Result<Self, Error> {
+    fn new(lock_request: &LockRequest) -> Result<Self, Error> {
         let request =
-            PowerRequest::new(POWER_DEFAULT_MESSAGE).map_err(Error::FailedToCreateRequest)?;
+            PowerRequest::new(&lock_request.reason).map_err(Error::FailedToCreateRequest)?;
+        let request_types = Self::request_types(&lock_request.kinds);
 
         let mut failed: Option<(LockTypeSuspend, DWORD)> = None;
 
-        for lock_type in REQUEST_TYPES.iter() {
+        for lock_type in request_types.iter() {
             let result =
-                unsafe { winbase::PowerSetRequest(request.0, Self::request_type(*lock_type)) };
+                unsafe { winbase::PowerSetRequest(request.handle, Self::request_type(lock_type)) };
             if result == 0 {
-                failed = Some((*lock_type, unsafe { errhandlingapi::GetLastError() }));
+                failed = Some((lock_type, unsafe { errhandlingapi::GetLastError() }));
                 break;
             }
         }
 
         match failed {
             Some((failed_type, err_code)) => {
-                for lock_type in REQUEST_TYPES.iter().take_while(|t| **t != failed_type) {
+                for lock_type in request_types.iter().take_while(|t| *t != failed_type) {
                     unsafe {
-                        winbase::PowerClearRequest(request.0, Self::request_type(*lock_type))
+                        winbase::PowerClearRequest(request.handle, Self::request_type(lock_type))
                     };
                 }
                 Err(Error::FailedToLock(err_code))
             }
-            None => Ok(Self { request }),
+            None => Ok(Self {
+                request,
+                request_types,
+            }),
         }
     }
 
+    fn request_types(kinds: &InhibitKinds) -> EnumSet<LockTypeSuspend> {
+        kinds.iter().fold(EnumSet::new(), |types, kind| match kind {
+            InhibitKind::SystemSleep => {
+                types | LockTypeSuspend::Automatic | LockTypeSuspend::Manual
+            }
+            InhibitKind::DisplaySleep => types | LockTypeSuspend::Display,
+            // The system idle timer is reset by the system required request
+            InhibitKind::Idle => types | LockTypeSuspend::Automatic,
+        })
+    }
+
     fn request_type(lock_type: LockTypeSuspend) -> POWER_REQUEST_TYPE {
         match lock_type {
             LockTypeSuspend::Automatic => winnt::PowerRequestSystemRequired,
@@ -85,25 +98,34 @@ impl Lock {
 
 impl Drop for Lock {
     fn drop(&mut self) {
-        for lock_type in REQUEST_TYPES.iter() {
-            unsafe { winbase::PowerClearRequest(self.request.0, Self::request_type(*lock_type)) };
+        for lock_type in self.request_types.iter() {
+            unsafe {
+                winbase::PowerClearRequest(self.request.handle, Self::request_type(lock_type))
+            };
         }
     }
 }
 
 unsafe impl Send for Lock {}
 
-impl crate::power::Lock for Lock {
+impl crate::power::Lock for Lock {}
+
+impl crate::power::Acquire for Lock {
     type Error = Error;
-    type Lock = Lock;
 
-    fn new() -> Result<Lock, Self::Error> {
-        Lock::new()
+    const SUPPORTED: &'static [InhibitKind] = crate::power::ALL_INHIBIT_KINDS;
+
+    fn acquire(request: &LockRequest) -> Result<Self, Self::Error> {
+        Lock::new(request)
     }
 }
 
 #[derive(Debug)]
-struct PowerRequest(HANDLE);
+struct PowerRequest {
+    handle: HANDLE,
+    /// NUL-terminated reason the request points to, kept for as long as the request exists
+    _reason: Vec<u16>,
+}
 
 impl PowerRequest {
     fn new(msg: &str) -> Result<Self, DWORD> {
@@ -112,20 +134,23 @@ impl PowerRequest {
             Flags: winnt::POWER_REQUEST_CONTEXT_SIMPLE_STRING,
             ..Default::default()
         };
-        let mut text: Vec<u16> = msg.encode_utf16().collect();
-        unsafe { *context.Reason.SimpleReasonString_mut() = text.as_mut_ptr() };
+        let mut reason: Vec<u16> = msg.encode_utf16().chain(Some(0)).collect();
+        unsafe { *context.Reason.SimpleReasonString_mut() = reason.as_mut_ptr() };
 
-        let request = unsafe { winbase::PowerCreateRequest(&mut context) };
-        if request.is_null() {
+        let handle = unsafe { winbase::PowerCreateRequest(&mut context) };
+        if handle.is_null() {
             Err(unsafe { errhandlingapi::GetLastError() })
         } else {
-            Ok(Self(request))
+            Ok(Self {
+                handle,
+                _reason: reason,
+            })
         }
     }
 }
 
 impl Drop for PowerRequest {
     fn drop(&mut self) {
-        unsafe { handleapi::CloseHandle(self.0) };
+        unsafe { handleapi::CloseHandle(self.handle) };
     }
 }
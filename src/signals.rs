@@ -0,0 +1,71 @@
+//! Process signals StayAwake reacts to
+
+use std::io;
+
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+
+/// Received signal
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Event {
+    /// The configuration should be reloaded (`SIGHUP`)
+    Reload,
+    /// The program should exit (`SIGINT` or Ctrl+C)
+    Interrupt,
+    /// The program should exit (`SIGTERM`)
+    Terminate,
+}
+
+#[cfg(unix)]
+impl Event {
+    /// Number of the signal behind this event
+    pub(crate) fn signal(&self) -> libc::c_int {
+        match self {
+            Event::Reload => libc::SIGHUP,
+            Event::Interrupt => libc::SIGINT,
+            Event::Terminate => libc::SIGTERM,
+        }
+    }
+}
+
+pub(crate) struct Signals {
+    #[cfg(unix)]
+    hangup: Signal,
+    #[cfg(unix)]
+    interrupt: Signal,
+    #[cfg(unix)]
+    terminate: Signal,
+}
+
+impl Signals {
+    pub(crate) fn new() -> io::Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            hangup: signal(SignalKind::hangup())?,
+            #[cfg(unix)]
+            interrupt: signal(SignalKind::interrupt())?,
+            #[cfg(unix)]
+            terminate: signal(SignalKind::terminate())?,
+        })
+    }
+
+    /// Waits for the next signal
+    #[cfg(unix)]
+    pub(crate) async fn recv(&mut self) -> Event {
+        tokio::select! {
+            _ = self.hangup.recv() => Event::Reload,
+            _ = self.interrupt.recv() => Event::Interrupt,
+            _ = self.terminate.recv() => Event::Terminate,
+        }
+    }
+
+    /// Waits for the next signal
+    #[cfg(not(unix))]
+    pub(crate) async fn recv(&mut self) -> Event {
+        match tokio::signal::ctrl_c().await {
+            Ok(()) => Event::Interrupt,
+            // Without a handler there is nothing to wait for
+            Err(_) => std::future::pending().await,
+        }
+    }
+}
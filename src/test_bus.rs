@@ -0,0 +1,83 @@
+//! Private `dbus-daemon` instances for testing D-Bus backends against stub services.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+};
+
+use tempfile::TempDir;
+use zbus::blocking::{Connection, ConnectionBuilder};
+
+const BUS_CONFIG: &str = r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:path={socket}</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>
+"#;
+
+/// A `dbus-daemon` listening on a socket in a temporary directory, killed on drop
+pub(crate) struct TestBus {
+    daemon: Child,
+    address: String,
+    _dir: TempDir,
+}
+
+impl TestBus {
+    /// Starts a new private bus, panics if `dbus-daemon` is not installed
+    pub(crate) fn start() -> Self {
+        let dir = tempfile::tempdir().expect("cannot create temporary directory");
+        let socket = dir.path().join("bus");
+        let config = dir.path().join("bus.conf");
+        fs::write(
+            &config,
+            BUS_CONFIG.replace("{socket}", &socket.to_string_lossy()),
+        )
+        .expect("cannot write bus config");
+
+        let mut daemon = Command::new("dbus-daemon")
+            .arg(format!("--config-file={}", config.display()))
+            .arg("--nofork")
+            .arg("--print-address")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("cannot start dbus-daemon");
+
+        let mut address = String::new();
+        let stdout = daemon.stdout.take().expect("dbus-daemon stdout is piped");
+        BufReader::new(stdout)
+            .read_line(&mut address)
+            .expect("cannot read bus address");
+
+        Self {
+            daemon,
+            address: address.trim().to_owned(),
+            _dir: dir,
+        }
+    }
+
+    /// Builder for a connection to this bus, e.g. to register a stub service
+    pub(crate) fn builder(&self) -> ConnectionBuilder<'static> {
+        ConnectionBuilder::address(self.address.as_str()).expect("invalid bus address")
+    }
+
+    /// Opens a new client connection to this bus
+    pub(crate) fn connect(&self) -> Connection {
+        self.builder().build().expect("cannot connect to test bus")
+    }
+}
+
+impl Drop for TestBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
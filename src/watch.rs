@@ -0,0 +1,220 @@
+//! Watching processes StayAwake did not start itself (`--watch-pid`)
+
+use std::{collections::BTreeMap, time::Duration};
+
+#[cfg(target_os = "linux")]
+use std::{fs, path::PathBuf};
+
+use tokio::time::{self, Interval, MissedTickBehavior};
+use tracing::info;
+
+/// How often the watched processes are checked
+const POLL_PERIOD: Duration = Duration::from_secs(1);
+
+/// Set of running processes, identified by their PIDs
+#[derive(Debug)]
+pub(crate) struct PidWatcher {
+    /// Processes that are still alive, with their names (if known)
+    alive: BTreeMap<u32, String>,
+    poll: Interval,
+    #[cfg(target_os = "linux")]
+    proc_root: PathBuf,
+}
+
+impl PidWatcher {
+    pub(crate) fn new(pids: &[u32]) -> Self {
+        Self::with_options(
+            pids,
+            POLL_PERIOD,
+            #[cfg(target_os = "linux")]
+            PathBuf::from("/proc"),
+        )
+    }
+
+    fn with_options(
+        pids: &[u32],
+        period: Duration,
+        #[cfg(target_os = "linux")] proc_root: PathBuf,
+    ) -> Self {
+        let mut poll = time::interval(period);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let mut watcher = Self {
+            alive: BTreeMap::new(),
+            poll,
+            #[cfg(target_os = "linux")]
+            proc_root,
+        };
+
+        for &pid in pids {
+            if watcher.is_alive(pid) {
+                let name = watcher.name(pid);
+                info!(pid, name, "Watching process");
+                watcher.alive.insert(pid, name);
+            } else {
+                info!(pid, "Watched process is not running");
+            }
+        }
+
+        watcher
+    }
+
+    /// Whether any of the watched processes is still running
+    pub(crate) fn any_alive(&self) -> bool {
+        !self.alive.is_empty()
+    }
+
+    /// Waits until all watched processes exit, returns the PID of the last one
+    ///
+    /// Never completes if none of the processes was running in the first place.
+    pub(crate) async fn wait(&mut self) -> u32 {
+        if self.alive.is_empty() {
+            return std::future::pending().await;
+        }
+
+        loop {
+            self.poll.tick().await;
+
+            let exited: Vec<u32> = self
+                .alive
+                .keys()
+                .copied()
+                .filter(|&pid| !self.is_alive(pid))
+                .collect();
+
+            for pid in exited {
+                let name = self.alive.remove(&pid).unwrap_or_default();
+                info!(
+                    pid,
+                    name,
+                    remaining = self.alive.len(),
+                    "Watched process exited"
+                );
+
+                if self.alive.is_empty() {
+                    return pid;
+                }
+            }
+        }
+    }
+
+    /// Reads the process name from `/proc/<pid>/comm`
+    #[cfg(target_os = "linux")]
+    fn name(&self, pid: u32) -> String {
+        fs::read_to_string(self.proc_root.join(pid.to_string()).join("comm"))
+            .map(|comm| comm.trim_end().to_owned())
+            .unwrap_or_default()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn name(&self, _pid: u32) -> String {
+        String::new()
+    }
+
+    /// Checks `/proc/<pid>/stat`, processes that are gone or zombies are not alive
+    #[cfg(target_os = "linux")]
+    fn is_alive(&self, pid: u32) -> bool {
+        let Ok(stat) = fs::read_to_string(self.proc_root.join(pid.to_string()).join("stat")) else {
+            return false;
+        };
+
+        // The state follows the process name, which is in parentheses and may contain anything
+        let state = stat
+            .rsplit_once(')')
+            .and_then(|(_, rest)| rest.split_whitespace().next());
+
+        !matches!(state, Some("Z" | "X" | "x"))
+    }
+
+    /// Probes the process with the null signal
+    #[cfg(all(unix, not(target_os = "linux")))]
+    fn is_alive(&self, pid: u32) -> bool {
+        let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+        // EPERM means the process exists but belongs to somebody else
+        result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+
+    #[cfg(windows)]
+    fn is_alive(&self, pid: u32) -> bool {
+        use winapi::{
+            shared::winerror,
+            um::{handleapi, processthreadsapi, synchapi, winnt},
+        };
+
+        unsafe {
+            let handle = processthreadsapi::OpenProcess(winnt::SYNCHRONIZE, 0, pid);
+            if handle.is_null() {
+                return false;
+            }
+
+            let running = synchapi::WaitForSingleObject(handle, 0) == winerror::WAIT_TIMEOUT;
+            handleapi::CloseHandle(handle);
+            running
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    fn fake_process(root: &std::path::Path, pid: u32, name: &str, state: &str) {
+        let dir = root.join(pid.to_string());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("comm"), format!("{name}\n")).unwrap();
+        fs::write(dir.join("stat"), format!("{pid} ({name}) {state} 1 1 1")).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_last_exited() {
+        let root = tempfile::tempdir().unwrap();
+        fake_process(root.path(), 100, "rsync", "S");
+        fake_process(root.path(), 200, "tar (gz)", "R");
+        fake_process(root.path(), 300, "defunct", "Z");
+
+        let mut watcher = PidWatcher::with_options(
+            &[100, 200, 300, 400],
+            Duration::from_millis(10),
+            root.path().to_owned(),
+        );
+        assert!(watcher.any_alive());
+        assert_eq!(
+            watcher.alive,
+            BTreeMap::from([(100, "rsync".to_owned()), (200, "tar (gz)".to_owned())])
+        );
+
+        fs::remove_dir_all(root.path().join("100")).unwrap();
+        let result = time::timeout(Duration::from_millis(100), watcher.wait()).await;
+        assert!(result.is_err());
+        assert_eq!(watcher.alive.len(), 1);
+
+        fake_process(root.path(), 200, "tar (gz)", "Z");
+        assert_eq!(watcher.wait().await, 200);
+        assert!(!watcher.any_alive());
+    }
+
+    #[tokio::test]
+    async fn test_real_process() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+
+        // Until the child calls exec it is a copy of the test process
+        let comm = PathBuf::from(format!("/proc/{}/comm", child.id()));
+        while fs::read_to_string(&comm).unwrap() != "sleep\n" {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let mut watcher = PidWatcher::with_options(
+            &[child.id()],
+            Duration::from_millis(10),
+            PathBuf::from("/proc"),
+        );
+        assert_eq!(watcher.alive.get(&child.id()).unwrap(), "sleep");
+
+        child.kill().unwrap();
+        assert_eq!(watcher.wait().await, child.id());
+        child.wait().unwrap();
+    }
+}